@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+use vrchatapi::apis::configuration::Configuration;
+
+use crate::config;
+use crate::output::SwitchRecord;
+use crate::switch::switch_and_record;
+
+pub const SOCKET_PATH: &str = "./vavc.sock";
+
+/// How long a client waits for the agent before doing the work itself.
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// One JSON object per line from the client.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum AgentRequest {
+    Ping,
+    Switch {
+        avatar_id: String,
+        resolved_by: String,
+        input: String,
+        /// The client's profile; the agent only switches for the one it was started with.
+        profile: String,
+    },
+}
+
+/// One JSON object per line back to the client.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum AgentResponse {
+    Pong,
+    Switched(SwitchRecord),
+    Error { error: String },
+}
+
+/// Serves switch requests over a Unix socket, reusing one API session for all of them.
+/// Clients still resolve aliases and queries themselves; only the switch is handed over.
+pub async fn run_agent(config: Configuration) -> Result<(), std::io::Error> {
+    if Path::new(SOCKET_PATH).exists() {
+        if UnixStream::connect(SOCKET_PATH).await.is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("Another agent is already listening on {}", SOCKET_PATH),
+            ));
+        }
+        // Left behind by an agent that did not shut down cleanly.
+        std::fs::remove_file(SOCKET_PATH)?;
+    }
+
+    let listener = bind_private(SOCKET_PATH)?;
+    info!(
+        "Agent listening on {} for profile '{}'",
+        SOCKET_PATH,
+        config::current().default_profile
+    );
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let config = config.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_client(config, stream).await {
+                            warn!("Agent client error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept agent client: {}", e),
+            },
+            _ = &mut ctrl_c => break,
+        }
+    }
+
+    std::fs::remove_file(SOCKET_PATH)?;
+    info!("Agent stopped");
+    Ok(())
+}
+
+/// Binds the socket so that only we can connect: anyone who can connect can switch our
+/// avatar. The umask applies at creation, so there is no moment the socket is open to others.
+fn bind_private(path: &str) -> Result<UnixListener, std::io::Error> {
+    // SAFETY: umask only swaps the process file mode mask, and is put back right away.
+    let previous = unsafe { libc::umask(0o177) };
+    let bound = UnixListener::bind(path);
+    unsafe { libc::umask(previous) };
+    bound
+}
+
+async fn handle_client(config: Configuration, stream: UnixStream) -> Result<(), std::io::Error> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str::<AgentRequest>(&line) {
+            Ok(AgentRequest::Ping) => AgentResponse::Pong,
+            Ok(AgentRequest::Switch { profile, .. })
+                if profile != config::current().default_profile =>
+            {
+                AgentResponse::Error {
+                    error: format!(
+                        "Agent is running for profile '{}', not '{}'",
+                        config::current().default_profile,
+                        profile
+                    ),
+                }
+            }
+            Ok(AgentRequest::Switch {
+                avatar_id,
+                resolved_by,
+                input,
+                ..
+            }) => AgentResponse::Switched(
+                switch_and_record(config.clone(), &avatar_id, &resolved_by, &input).await,
+            ),
+            Err(e) => AgentResponse::Error {
+                error: format!("Invalid request: {}", e),
+            },
+        };
+
+        let mut body = serde_json::to_string(&response).expect("Response is not serializable");
+        body.push('\n');
+        writer.write_all(body.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+/// Hands the switch to a running agent. Returns `None` if there is no agent to talk to,
+/// in which case the caller should switch by itself.
+pub async fn switch_via_agent(
+    avatar_id: &str,
+    resolved_by: &str,
+    input: &str,
+) -> Option<SwitchRecord> {
+    let request = AgentRequest::Switch {
+        avatar_id: avatar_id.to_string(),
+        resolved_by: resolved_by.to_string(),
+        input: input.to_string(),
+        profile: config::current().default_profile.clone(),
+    };
+
+    match request_agent(&request).await {
+        Ok(AgentResponse::Switched(record)) => Some(record),
+        Ok(AgentResponse::Error { error }) => {
+            info!("Not using the agent: {}", error);
+            None
+        }
+        Ok(other) => {
+            warn!("Unexpected agent response: {:?}", other);
+            None
+        }
+        Err(e) => {
+            debug!("Agent not available: {}", e);
+            None
+        }
+    }
+}
+
+async fn request_agent(request: &AgentRequest) -> Result<AgentResponse, std::io::Error> {
+    if !Path::new(SOCKET_PATH).exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} does not exist", SOCKET_PATH),
+        ));
+    }
+
+    let stream = timeout(CONNECT_TIMEOUT, UnixStream::connect(SOCKET_PATH))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "connect timed out"))??;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut body = serde_json::to_string(request).expect("Request is not serializable");
+    body.push('\n');
+    writer.write_all(body.as_bytes()).await?;
+
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "no response"))?;
+
+    serde_json::from_str(&line).map_err(std::io::Error::other)
+}
+
+/// Whether an agent is up and answering.
+pub async fn ping() -> bool {
+    matches!(
+        request_agent(&AgentRequest::Ping).await,
+        Ok(AgentResponse::Pong)
+    )
+}
@@ -0,0 +1,272 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::warn;
+
+use crate::db::{self, AliasChange, Selector};
+use crate::output::AliasChangeRecord;
+use crate::switch::resolve_selector;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AliasFileFormat {
+    Json,
+    Toml,
+    Csv,
+}
+
+impl AliasFileFormat {
+    /// Guesses the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "json" => Some(AliasFileFormat::Json),
+            "toml" => Some(AliasFileFormat::Toml),
+            "csv" => Some(AliasFileFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// One alias in a shared file. Entries with `match_name` or `match_query` become
+/// selector aliases; otherwise a missing `avatar_id` is looked up by `avatar_name`
+/// in the local database.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AliasEntry {
+    pub alias: String,
+    #[serde(default)]
+    pub avatar_id: Option<String>,
+    #[serde(default)]
+    pub avatar_name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub match_name: Option<String>,
+    #[serde(default)]
+    pub match_author: Option<String>,
+    #[serde(default)]
+    pub match_query: Option<String>,
+}
+
+impl AliasEntry {
+    fn selector(&self) -> Option<Selector> {
+        match (&self.match_name, &self.match_query) {
+            (Some(name), _) => Some(Selector::Name {
+                name: name.clone(),
+                author: self.match_author.clone(),
+            }),
+            (None, Some(query)) => Some(Selector::Query(query.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TomlFile {
+    #[serde(default)]
+    alias: Vec<AliasEntry>,
+}
+
+/// A change an import would make, along with the selector and description to store.
+pub struct ImportStep {
+    pub record: AliasChangeRecord,
+    selector: Option<Selector>,
+    description: Option<String>,
+}
+
+pub fn export_aliases(format: AliasFileFormat) -> Result<String, Box<dyn std::error::Error>> {
+    db::create_avatar_db()?;
+    db::create_alias_db()?;
+
+    let names: HashMap<String, String> = db::get_all_avatars()?
+        .into_iter()
+        .map(|avatar| (avatar.id, avatar.name))
+        .collect();
+    let entries: Vec<AliasEntry> = db::get_all_aliases()?
+        .into_iter()
+        .map(|alias| {
+            let (match_name, match_author, match_query) = match alias.selector {
+                Some(Selector::Name { name, author }) => (Some(name), author, None),
+                Some(Selector::Query(query)) => (None, None, Some(query)),
+                None => (None, None, None),
+            };
+            AliasEntry {
+                avatar_name: names.get(&alias.avatar_id).cloned().or(alias.avatar_name),
+                avatar_id: Some(alias.avatar_id),
+                alias: alias.name,
+                description: alias.description,
+                match_name,
+                match_author,
+                match_query,
+            }
+        })
+        .collect();
+
+    let content = match format {
+        AliasFileFormat::Json => serde_json::to_string_pretty(&entries)? + "\n",
+        AliasFileFormat::Toml => toml::to_string(&TomlFile { alias: entries })?,
+        AliasFileFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for entry in &entries {
+                writer.serialize(entry)?;
+            }
+            String::from_utf8(writer.into_inner()?)?
+        }
+    };
+
+    Ok(content)
+}
+
+pub fn read_alias_file(
+    path: &Path,
+    format: AliasFileFormat,
+) -> Result<Vec<AliasEntry>, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)?;
+
+    let entries = match format {
+        AliasFileFormat::Json => serde_json::from_str(&content)?,
+        AliasFileFormat::Toml => toml::from_str::<TomlFile>(&content)?.alias,
+        AliasFileFormat::Csv => csv::Reader::from_reader(content.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<AliasEntry>, _>>()?,
+    };
+
+    Ok(entries)
+}
+
+/// Works out what importing `entries` would change without touching the database.
+/// With `replace`, aliases missing from the file are removed.
+pub fn plan_import(
+    entries: Vec<AliasEntry>,
+    replace: bool,
+) -> Result<Vec<ImportStep>, rusqlite::Error> {
+    db::create_avatar_db()?;
+    db::create_alias_db()?;
+
+    let avatars = db::get_all_avatars()?;
+    let mut steps = Vec::new();
+    let mut in_file = HashSet::new();
+
+    for entry in entries {
+        in_file.insert(entry.alias.clone());
+
+        let selector = entry.selector();
+        let resolved = match (&selector, &entry.avatar_id, &entry.avatar_name) {
+            (Some(selector), _, _) => resolve_selector(selector).map(|avatar| avatar.id),
+            (None, Some(avatar_id), _) => {
+                if !avatars.iter().any(|avatar| avatar.id == *avatar_id) {
+                    warn!(
+                        "Alias '{}': avatar ID '{}' is not in the local database",
+                        entry.alias, avatar_id
+                    );
+                }
+                Ok(avatar_id.clone())
+            }
+            (None, None, Some(name)) => {
+                let matches: Vec<&str> = avatars
+                    .iter()
+                    .filter(|avatar| avatar.name == *name)
+                    .map(|avatar| avatar.id.as_str())
+                    .collect();
+                match matches.as_slice() {
+                    [avatar_id] => Ok(avatar_id.to_string()),
+                    [] => Err(format!("No avatar named '{}'", name)),
+                    many => Err(format!(
+                        "{} avatars named '{}'; give an avatar_id",
+                        many.len(),
+                        name
+                    )),
+                }
+            }
+            (None, None, None) => Err(String::from("Neither avatar_id nor avatar_name given")),
+        };
+
+        let existing = db::get_alias(&entry.alias)?;
+        let previous_avatar_id = existing.as_ref().map(|alias| alias.avatar_id.clone());
+        let record = match resolved {
+            Ok(avatar_id) => {
+                let result = match &existing {
+                    None => AliasChange::Created,
+                    Some(alias)
+                        if alias.avatar_id == avatar_id
+                            && alias.selector == selector
+                            && entry
+                                .description
+                                .as_ref()
+                                .is_none_or(|d| alias.description.as_ref() == Some(d)) =>
+                    {
+                        AliasChange::Unchanged
+                    }
+                    Some(_) => AliasChange::Updated,
+                };
+                AliasChangeRecord {
+                    alias: entry.alias,
+                    result,
+                    avatar_id: Some(avatar_id),
+                    previous_avatar_id,
+                    warning: None,
+                }
+            }
+            Err(e) => AliasChangeRecord {
+                alias: entry.alias,
+                result: AliasChange::NotFound,
+                avatar_id: None,
+                previous_avatar_id,
+                warning: Some(e),
+            },
+        };
+
+        steps.push(ImportStep {
+            record,
+            selector,
+            description: entry.description,
+        });
+    }
+
+    if replace {
+        for alias in db::get_all_aliases()? {
+            if !in_file.contains(&alias.name) {
+                steps.push(ImportStep {
+                    record: AliasChangeRecord {
+                        alias: alias.name,
+                        result: AliasChange::Removed,
+                        avatar_id: None,
+                        previous_avatar_id: Some(alias.avatar_id),
+                        warning: None,
+                    },
+                    selector: None,
+                    description: None,
+                });
+            }
+        }
+    }
+
+    Ok(steps)
+}
+
+/// Applies the planned changes in one transaction, so a failure leaves the aliases as they were.
+pub fn apply_import(steps: &[ImportStep]) -> Result<(), rusqlite::Error> {
+    let mut conn = db::open()?;
+    let tx = conn.transaction()?;
+
+    for step in steps {
+        let record = &step.record;
+        match (record.result, &record.avatar_id) {
+            (AliasChange::Created | AliasChange::Updated, Some(avatar_id)) => {
+                db::register_alias_in(
+                    &tx,
+                    &record.alias,
+                    avatar_id,
+                    step.selector.as_ref(),
+                    step.description.as_deref(),
+                    true,
+                )?;
+            }
+            (AliasChange::Removed, _) => {
+                db::remove_alias_in(&tx, &record.alias)?;
+            }
+            _ => {}
+        }
+    }
+
+    tx.commit()
+}
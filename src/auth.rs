@@ -1,11 +1,13 @@
 use reqwest::{cookie::CookieStore, header::HeaderValue};
-use std::{str::FromStr, sync::Arc};
+use std::sync::Arc;
 use vrchatapi::{
-    apis::{self, configuration::Configuration},
+    apis::{self, authentication_api::VerifyAuthTokenError, configuration::Configuration},
     models::{EitherUserOrTwoFactor, TwoFactorAuthCode, TwoFactorEmailCode},
 };
 
 use crate::{
+    config,
+    log::{trace_request, trace_response},
     read_user_input,
     secret::{read_secret_in_directory, write_secret_in_directory},
 };
@@ -23,8 +25,9 @@ pub async fn get_new_auth_cookie(username: Option<String>, password: Option<Stri
     let jar = Arc::new(reqwest::cookie::Jar::default());
 
     let config = apis::configuration::Configuration {
+        base_path: config::current().api_base_url.clone(),
         basic_auth: Some((username, Some(password))),
-        user_agent: Some(String::from("my-rust-client/1.0.0")),
+        user_agent: Some(config::current().user_agent.clone()),
         client: reqwest::Client::builder()
             .cookie_provider(jar.clone())
             .build()
@@ -78,42 +81,55 @@ pub async fn get_new_auth_cookie(username: Option<String>, password: Option<Stri
     }
 }
 
-pub async fn check_auth_cookie() {
+pub async fn check_auth_cookie() -> Result<(), apis::Error<VerifyAuthTokenError>> {
     let config = make_configuration_with_cookies();
 
-    match apis::authentication_api::verify_auth_token(&config).await {
-        Ok(_) => println!("Auth cookie is valid"),
-        Err(e) => eprintln!("Auth cookie is invalid: {}", e),
-    }
+    trace_request("verify_auth_token", "");
+    let result = apis::authentication_api::verify_auth_token(&config).await;
+    trace_response("verify_auth_token", &result);
+
+    result.map(|_| ())
 }
 
 pub fn make_configuration_with_cookies() -> Configuration {
-    let jar = Arc::new(reqwest::cookie::Jar::default());
-    match read_secret_in_directory() {
-        Some(cookies) => {
-            jar.set_cookies(
-                &mut [HeaderValue::from_str(&format!(
-                    "{}; {}",
-                    cookies.first().unwrap(),
-                    cookies.get(1).unwrap()
-                ))
-                .expect("Invalid cookie string")]
-                .iter(),
-                &url::Url::from_str("https://api.vrchat.cloud").expect("Invalid URL"),
-            );
-        }
+    match try_make_configuration_with_cookies() {
+        Some(configuration) => configuration,
         None => {
             eprintln!("No auth cookie found. Please authenticate first.");
             std::process::exit(1);
         }
     }
+}
 
-    apis::configuration::Configuration {
-        user_agent: Some(String::from("my-rust-client/1.0.0")),
+/// Like `make_configuration_with_cookies`, but `None` instead of exiting without a cookie.
+pub fn try_make_configuration_with_cookies() -> Option<Configuration> {
+    let cookies = read_secret_in_directory()?;
+    let origin = match config::current().api_origin() {
+        Ok(origin) => origin,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let jar = Arc::new(reqwest::cookie::Jar::default());
+    jar.set_cookies(
+        &mut [HeaderValue::from_str(&format!(
+            "{}; {}",
+            cookies.first().unwrap(),
+            cookies.get(1).unwrap()
+        ))
+        .expect("Invalid cookie string")]
+        .iter(),
+        &origin,
+    );
+
+    Some(apis::configuration::Configuration {
+        base_path: config::current().api_base_url.clone(),
+        user_agent: Some(config::current().user_agent.clone()),
         client: reqwest::Client::builder()
             .cookie_provider(jar.clone())
             .build()
             .unwrap(),
         ..Default::default()
-    }
+    })
 }
@@ -0,0 +1,340 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::info;
+use vrchatapi::apis::{self, configuration::Configuration};
+use vrchatapi::models::{Avatar, ReleaseStatus, UpdateAvatarRequest};
+
+use crate::db::Origin;
+use crate::log::{trace_request, trace_response};
+use crate::output::Record;
+use crate::switch::{Target, resolve_target};
+use crate::{db, ratelimit};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Release {
+    Public,
+    Private,
+    Hidden,
+}
+
+impl From<Release> for ReleaseStatus {
+    fn from(release: Release) -> Self {
+        match release {
+            Release::Public => ReleaseStatus::Public,
+            Release::Private => ReleaseStatus::Private,
+            Release::Hidden => ReleaseStatus::Hidden,
+        }
+    }
+}
+
+/// Prefix of the tags an avatar's author chooses. Other tags, such as `content_*`
+/// ratings and `admin_*` tags, are kept when the author tags are replaced.
+const AUTHOR_TAG: &str = "author_tag_";
+
+/// The fields to change on one avatar. Fields left out are kept as they are; in a CSV
+/// file an empty cell counts as left out, so a field cannot be cleared from CSV.
+/// `tags` is comma separated and replaces only the author tags (see `AUTHOR_TAG`).
+#[derive(Debug, Default, Deserialize)]
+pub struct AvatarEdit {
+    pub avatar: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub release_status: Option<Release>,
+}
+
+impl AvatarEdit {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.description.is_none()
+            && self.tags.is_none()
+            && self.release_status.is_none()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AvatarChange {
+    pub avatar_id: String,
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+impl Record for AvatarChange {
+    const COLUMNS: &'static [&'static str] = &["avatar_id", "field", "old", "new"];
+}
+
+/// The changes an edit would make, along with the request that makes them.
+pub struct EditPlan {
+    pub avatar_id: String,
+    pub current: Avatar,
+    pub changes: Vec<AvatarChange>,
+    request: UpdateAvatarRequest,
+}
+
+/// Reads bulk edits from a JSON array or a CSV file with a header line.
+pub fn read_edit_file(path: &Path) -> Result<Vec<AvatarEdit>, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+
+    let edits = match extension.as_deref() {
+        Some("json") => serde_json::from_str(&content)?,
+        Some("csv") => csv::Reader::from_reader(content.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<AvatarEdit>, _>>()?,
+        _ => return Err("Edit files must end in .json or .csv".into()),
+    };
+
+    Ok(edits)
+}
+
+/// Takes an avatar ID as is and anything else as an alias.
+pub fn resolve_avatar(avatar: &str) -> Result<String, String> {
+    resolve_target(&Target::alias_or_id(avatar))
+}
+
+pub async fn get_avatar(config: &Configuration, avatar_id: &str) -> Result<Avatar, String> {
+    ratelimit::api().wait().await;
+    trace_request("get_avatar", avatar_id);
+    let result = apis::avatars_api::get_avatar(config, avatar_id).await;
+    trace_response("get_avatar", &result);
+    result.map_err(|e| format!("Failed to get avatar {}: {}", avatar_id, e))
+}
+
+/// Returns an avatar from the local database, fetching it from the API and caching it
+/// as external if it is not there yet.
+pub async fn lookup_avatar(config: &Configuration, avatar_id: &str) -> Result<Avatar, String> {
+    match db::get_avatar_by_id(avatar_id) {
+        Ok(Some(avatar)) => return Ok(avatar),
+        Ok(None) => {}
+        Err(e) => return Err(format!("Error retrieving avatar from database: {}", e)),
+    }
+
+    let avatar = get_avatar(config, avatar_id).await?;
+    db::save_avatar(avatar.clone(), Origin::External)
+        .map_err(|e| format!("Error storing avatar: {}", e))?;
+    info!("Cached external avatar: {} ({})", avatar.name, avatar.id);
+    Ok(avatar)
+}
+
+/// Compares an edit with the avatar as the API has it now.
+pub async fn plan_edit(config: &Configuration, edit: &AvatarEdit) -> Result<EditPlan, String> {
+    let avatar_id = resolve_avatar(&edit.avatar)?;
+    let current = get_avatar(config, &avatar_id).await?;
+
+    let mut changes = Vec::new();
+    let mut request = UpdateAvatarRequest::new();
+    let mut change = |field, old: String, new: String| {
+        let changed = old != new;
+        if changed {
+            changes.push(AvatarChange {
+                avatar_id: avatar_id.clone(),
+                field,
+                old,
+                new,
+            });
+        }
+        changed
+    };
+
+    if let Some(name) = &edit.name
+        && change("name", current.name.clone(), name.clone())
+    {
+        request.name = Some(name.clone());
+    }
+    if let Some(description) = &edit.description
+        && change(
+            "description",
+            current.description.clone(),
+            description.clone(),
+        )
+    {
+        request.description = Some(description.clone());
+    }
+    if let Some(tags) = &edit.tags {
+        let author_tags: Vec<String> = tags
+            .split(',')
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| tag.strip_prefix(AUTHOR_TAG).unwrap_or(tag).to_string())
+            .collect();
+        let (current_author_tags, other_tags): (Vec<&String>, Vec<&String>) = current
+            .tags
+            .iter()
+            .partition(|tag| tag.starts_with(AUTHOR_TAG));
+        let current_author_tags: Vec<&str> = current_author_tags
+            .iter()
+            .map(|tag| &tag[AUTHOR_TAG.len()..])
+            .collect();
+
+        if change("tags", current_author_tags.join(","), author_tags.join(",")) {
+            request.tags = Some(
+                other_tags
+                    .into_iter()
+                    .cloned()
+                    .chain(
+                        author_tags
+                            .iter()
+                            .map(|tag| format!("{}{}", AUTHOR_TAG, tag)),
+                    )
+                    .collect(),
+            );
+        }
+    }
+    if let Some(release) = edit.release_status {
+        let release = ReleaseStatus::from(release);
+        if change(
+            "release_status",
+            current.release_status.to_string(),
+            release.to_string(),
+        ) {
+            request.release_status = Some(release);
+        }
+    }
+
+    Ok(EditPlan {
+        avatar_id,
+        current,
+        changes,
+        request,
+    })
+}
+
+/// Sends the changes to the API and stores the updated avatar locally.
+pub async fn apply_edit(config: &Configuration, plan: EditPlan) -> Result<(), String> {
+    if plan.changes.is_empty() {
+        return Ok(());
+    }
+
+    ratelimit::api().wait().await;
+    trace_request(
+        "update_avatar",
+        &format!(
+            "{} {}",
+            plan.avatar_id,
+            serde_json::to_string(&plan.request).unwrap_or_default()
+        ),
+    );
+    let result =
+        apis::avatars_api::update_avatar(config, &plan.avatar_id, Some(plan.request)).await;
+    trace_response("update_avatar", &result);
+    let avatar = result.map_err(|e| format!("Failed to update {}: {}", plan.avatar_id, e))?;
+
+    db::save_avatar(avatar, Origin::Own).map_err(|e| format!("Error storing avatar: {}", e))?;
+    info!(
+        "Updated {} ({} change(s))",
+        plan.avatar_id,
+        plan.changes.len()
+    );
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseResult {
+    /// Would change; only seen in dry runs.
+    Planned,
+    Updated,
+    Unchanged,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReleaseRecord {
+    pub avatar_id: String,
+    pub avatar_name: Option<String>,
+    pub from: Option<String>,
+    pub to: String,
+    pub result: ReleaseResult,
+    pub error: Option<String>,
+}
+
+impl Record for ReleaseRecord {
+    const COLUMNS: &'static [&'static str] =
+        &["avatar_id", "avatar_name", "from", "to", "result", "error"];
+}
+
+/// A release status change to make, along with the edit that makes it.
+pub struct ReleaseStep {
+    pub record: ReleaseRecord,
+    plan: Option<EditPlan>,
+}
+
+/// Looks up the current release status of each avatar. Nothing is changed yet.
+pub async fn plan_release(
+    config: &Configuration,
+    avatar_ids: &[String],
+    release: Release,
+) -> Vec<ReleaseStep> {
+    let to = ReleaseStatus::from(release).to_string();
+    let mut steps = Vec::new();
+
+    for avatar_id in avatar_ids {
+        let edit = AvatarEdit {
+            avatar: avatar_id.clone(),
+            release_status: Some(release),
+            ..Default::default()
+        };
+        let step = match plan_edit(config, &edit).await {
+            Ok(plan) => ReleaseStep {
+                record: ReleaseRecord {
+                    avatar_id: avatar_id.clone(),
+                    avatar_name: Some(plan.current.name.clone()),
+                    from: Some(plan.current.release_status.to_string()),
+                    to: to.clone(),
+                    result: if plan.changes.is_empty() {
+                        ReleaseResult::Unchanged
+                    } else {
+                        ReleaseResult::Planned
+                    },
+                    error: None,
+                },
+                plan: Some(plan),
+            },
+            Err(e) => ReleaseStep {
+                record: ReleaseRecord {
+                    avatar_id: avatar_id.clone(),
+                    avatar_name: None,
+                    from: None,
+                    to: to.clone(),
+                    result: ReleaseResult::Failed,
+                    error: Some(e),
+                },
+                plan: None,
+            },
+        };
+        steps.push(step);
+    }
+
+    steps
+}
+
+/// Carries out the planned steps, reporting how each one went.
+pub async fn apply_release(config: &Configuration, steps: Vec<ReleaseStep>) -> Vec<ReleaseRecord> {
+    let mut records = Vec::new();
+
+    for step in steps {
+        let mut record = step.record;
+        if let (ReleaseResult::Planned, Some(plan)) = (record.result, step.plan) {
+            match apply_edit(config, plan).await {
+                Ok(()) => record.result = ReleaseResult::Updated,
+                Err(e) => {
+                    record.result = ReleaseResult::Failed;
+                    record.error = Some(e);
+                }
+            }
+        }
+        records.push(record);
+    }
+
+    records
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::output::OutputFormat;
+
+const DEFAULT_CONFIG_PATH: &str = "./config.toml";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub api_base_url: String,
+    pub user_agent: String,
+    pub fetch_page_size: i32,
+    pub fetch_delay_secs: u64,
+    pub default_profile: String,
+    pub default_output: OutputFormat,
+    pub default_platform: Option<String>,
+    pub fallback_avatar: Option<String>,
+    pub pipeline_url: String,
+    pub hooks: Vec<Hook>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            api_base_url: String::from("https://api.vrchat.cloud/api/1"),
+            user_agent: String::from("my-rust-client/1.0.0"),
+            fetch_page_size: 60,
+            fetch_delay_secs: 5,
+            default_profile: String::from("default"),
+            default_output: OutputFormat::Table,
+            default_platform: None,
+            fallback_avatar: None,
+            pipeline_url: String::from("wss://pipeline.vrchat.cloud/"),
+            hooks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookWhen {
+    /// Before switching; a non-zero exit cancels the switch.
+    Pre,
+    /// After a successful switch.
+    Post,
+}
+
+/// A shell command run around avatar switches, from `[[hooks]]` in the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    pub when: HookWhen,
+    pub command: String,
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_hook_timeout() -> u64 {
+    10
+}
+
+/// Environment variables that override the matching config keys.
+const ENV_OVERRIDES: [(&str, &str); 9] = [
+    ("api_base_url", "VAVC_API_BASE_URL"),
+    ("user_agent", "VAVC_USER_AGENT"),
+    ("fetch_page_size", "VAVC_FETCH_PAGE_SIZE"),
+    ("fetch_delay_secs", "VAVC_FETCH_DELAY_SECS"),
+    ("default_profile", "VAVC_PROFILE"),
+    ("default_output", "VAVC_OUTPUT"),
+    ("default_platform", "VAVC_PLATFORM"),
+    ("fallback_avatar", "VAVC_FALLBACK_AVATAR"),
+    ("pipeline_url", "VAVC_PIPELINE_URL"),
+];
+
+impl Config {
+    /// Scheme and host of the API, which is where the auth cookies belong.
+    pub fn api_origin(&self) -> Result<url::Url, String> {
+        let url = parse_url("api_base_url", &self.api_base_url, &["http", "https"])?;
+        url.join("/")
+            .map_err(|e| format!("Invalid api_base_url: {}", e))
+    }
+
+    /// Checks the values serde cannot, such as the URLs being usable.
+    fn validate(&self) -> Result<(), String> {
+        self.api_origin()?;
+        parse_url("pipeline_url", &self.pipeline_url, &["ws", "wss"])?;
+        Ok(())
+    }
+
+    /// Cookie file for the selected profile.
+    pub fn secret_path(&self) -> String {
+        if self.default_profile == "default" {
+            String::from("./secret")
+        } else {
+            format!("./secret.{}", self.default_profile)
+        }
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    std::env::var("VAVC_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_CONFIG_PATH))
+}
+
+pub fn keys() -> Vec<&'static str> {
+    ENV_OVERRIDES.iter().map(|(key, _)| *key).collect()
+}
+
+/// Reads the config file (if any) and applies environment overrides on top.
+pub fn load() -> Result<Config, String> {
+    let mut table = read_table()?;
+
+    for (key, env) in ENV_OVERRIDES {
+        if let Ok(value) = std::env::var(env) {
+            table.insert(key.to_string(), parse_value(key, &value)?);
+        }
+    }
+
+    let config: Config = toml::Value::Table(table)
+        .try_into()
+        .map_err(|e| format!("Invalid configuration: {}", e))?;
+    config
+        .validate()
+        .map_err(|e| format!("Invalid configuration: {}", e))?;
+    Ok(config)
+}
+
+/// Writes a single key to the config file, keeping the other keys as they are.
+pub fn set(key: &str, value: &str) -> Result<(), String> {
+    let mut table = read_table()?;
+    table.insert(key.to_string(), parse_value(key, value)?);
+
+    // Make sure the result is still a valid config before writing it.
+    toml::Value::Table(table.clone())
+        .try_into::<Config>()
+        .map_err(|e| e.to_string())
+        .and_then(|config| config.validate())
+        .map_err(|e| format!("Invalid value for '{}': {}", key, e))?;
+
+    let content = toml::to_string(&table).map_err(|e| e.to_string())?;
+    std::fs::write(config_path(), content)
+        .map_err(|e| format!("Failed to write {}: {}", config_path().display(), e))
+}
+
+/// Looks up a key in an already loaded config, formatted for display.
+pub fn get(config: &Config, key: &str) -> Result<String, String> {
+    let value = toml::Value::try_from(config).map_err(|e| e.to_string())?;
+
+    match value.get(key) {
+        Some(toml::Value::String(s)) => Ok(s.clone()),
+        Some(other) => Ok(other.to_string()),
+        None if keys().contains(&key) => Ok(String::new()),
+        None => Err(format!("Unknown config key '{}'", key)),
+    }
+}
+
+pub fn init(config: Config) {
+    CONFIG.set(config).expect("Config initialized twice");
+}
+
+pub fn current() -> &'static Config {
+    CONFIG.get().expect("Config not initialized")
+}
+
+fn read_table() -> Result<toml::Table, String> {
+    match std::fs::read_to_string(config_path()) {
+        Ok(content) => content
+            .parse::<toml::Table>()
+            .map_err(|e| format!("Failed to parse {}: {}", config_path().display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(toml::Table::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", config_path().display(), e)),
+    }
+}
+
+fn parse_value(key: &str, value: &str) -> Result<toml::Value, String> {
+    match key {
+        "fetch_page_size" | "fetch_delay_secs" => value
+            .parse::<i64>()
+            .map(toml::Value::Integer)
+            .map_err(|_| format!("'{}' must be a number", key)),
+        _ if keys().contains(&key) => Ok(toml::Value::String(value.to_string())),
+        _ => Err(format!("Unknown config key '{}'", key)),
+    }
+}
+
+/// Parses a URL-valued key, accepting only the given schemes.
+fn parse_url(key: &str, value: &str, schemes: &[&str]) -> Result<url::Url, String> {
+    let url = url::Url::parse(value).map_err(|e| format!("Invalid {}: {}", key, e))?;
+    if !schemes.contains(&url.scheme()) {
+        return Err(format!(
+            "Invalid {}: expected a {} URL",
+            key,
+            schemes.join(" or ")
+        ));
+    }
+    if url.host_str().is_none() {
+        return Err(format!("Invalid {}: URL has no host", key));
+    }
+    Ok(url)
+}
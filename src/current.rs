@@ -0,0 +1,64 @@
+use std::time::Duration;
+use tokio::time::interval;
+use tracing::{info, warn};
+use vrchatapi::apis::configuration::Configuration;
+
+use crate::avatar::lookup_avatar;
+use crate::db;
+use crate::output::{CurrentRecord, OutputFormat, print_record};
+use crate::switch::get_current_user;
+
+/// Asks the API what we are wearing and names it from the local database and aliases.
+pub async fn get_current(config: &Configuration) -> Result<CurrentRecord, String> {
+    let user = get_current_user(config).await?;
+    let avatar_id = user.current_avatar;
+
+    // Avatars that are not ours can still be named, and are cached for next time.
+    let avatar_name = match lookup_avatar(config, &avatar_id).await {
+        Ok(avatar) => Some(avatar.name),
+        Err(e) => {
+            warn!("Could not look up {}: {}", avatar_id, e);
+            None
+        }
+    };
+    let aliases = db::get_all_aliases()
+        .map_err(|e| format!("Error retrieving aliases from database: {}", e))?
+        .into_iter()
+        .filter(|alias| alias.avatar_id == avatar_id)
+        .map(|alias| alias.name)
+        .collect();
+
+    Ok(CurrentRecord {
+        avatar_id,
+        avatar_name,
+        aliases,
+        thumbnail_image_url: user.current_avatar_thumbnail_image_url,
+    })
+}
+
+/// Polls the current avatar and prints it whenever it changes, until interrupted.
+pub async fn watch_current(config: &Configuration, output: OutputFormat, every: Duration) {
+    let mut last_avatar_id = None;
+    let mut ticker = interval(every);
+    // One future for the whole loop, so a Ctrl-C during a request is not lost.
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => match get_current(config).await {
+                Ok(record) => {
+                    if last_avatar_id.as_ref() != Some(&record.avatar_id) {
+                        if last_avatar_id.is_some() {
+                            info!("Avatar changed to {}", record.avatar_id);
+                        }
+                        last_avatar_id = Some(record.avatar_id.clone());
+                        print_record(output, &record);
+                    }
+                }
+                Err(e) => warn!("{}", e),
+            },
+            _ = &mut ctrl_c => return,
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use chrono::{DateTime, Datelike, Days, Local, NaiveTime, SecondsFormat, TimeDelta, Weekday};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::interval;
+use tracing::{error, info, warn};
+use vrchatapi::apis::configuration::Configuration;
+
+use crate::db;
+use crate::hooks;
+use crate::pipeline;
+use crate::switch::{Target, resolve_target, switch_and_record};
+
+const TICK: Duration = Duration::from_secs(30);
+
+/// A missed `at` rule still fires if the daemon comes up this soon afterwards.
+const GRACE: TimeDelta = TimeDelta::minutes(5);
+
+#[derive(Debug, Deserialize)]
+struct ScheduleFile {
+    /// Alias or avatar ID to switch back to when the avatar is changed in-game.
+    enforce: Option<String>,
+    #[serde(default)]
+    rule: Vec<RuleFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    name: String,
+    at: Option<String>,
+    days: Option<String>,
+    every: Option<String>,
+    id: Option<String>,
+    alias: Option<String>,
+    query: Option<String>,
+    rotate: Option<Vec<String>>,
+    group: Option<String>,
+}
+
+#[derive(Debug)]
+enum When {
+    At { time: NaiveTime, days: Vec<Weekday> },
+    Every(TimeDelta),
+}
+
+#[derive(Debug)]
+enum Action {
+    Switch(Target),
+    /// Aliases or avatar IDs, worn one after another each time the rule fires.
+    Rotate(Vec<String>),
+    /// Like `Rotate`, over the aliases in a group as it is when the rule fires.
+    RotateGroup(String),
+}
+
+#[derive(Debug)]
+pub struct Schedule {
+    pub rules: Vec<Rule>,
+    pub enforce: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    name: String,
+    when: When,
+    action: Action,
+}
+
+pub fn load_schedule(path: &Path) -> Result<Schedule, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let file: ScheduleFile = toml::from_str(&content)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+    // The daemon keeps each rule's state under its name, so names must not repeat.
+    let mut names = HashSet::new();
+    if let Some(rule) = file.rule.iter().find(|rule| !names.insert(&rule.name)) {
+        return Err(format!("Rule '{}' is defined more than once", rule.name));
+    }
+
+    let rules = file
+        .rule
+        .into_iter()
+        .map(|rule| {
+            let name = rule.name.clone();
+            parse_rule(rule).map_err(|e| format!("Rule '{}': {}", name, e))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Schedule {
+        rules,
+        enforce: file.enforce,
+    })
+}
+
+fn parse_rule(rule: RuleFile) -> Result<Rule, String> {
+    let when = match (&rule.at, &rule.every) {
+        (Some(at), None) => When::At {
+            time: NaiveTime::parse_from_str(at, "%H:%M")
+                .map_err(|_| format!("'at' must look like 09:00, got '{}'", at))?,
+            days: parse_days(rule.days.as_deref().unwrap_or("daily"))?,
+        },
+        (None, Some(every)) => {
+            if rule.days.is_some() {
+                return Err(String::from("'days' only applies to 'at' rules"));
+            }
+            When::Every(parse_every(every)?)
+        }
+        _ => return Err(String::from("exactly one of 'at' or 'every' is required")),
+    };
+
+    let action = match (rule.id, rule.alias, rule.query, rule.rotate, rule.group) {
+        (Some(id), None, None, None, None) => Action::Switch(Target::Id(id)),
+        (None, Some(alias), None, None, None) => Action::Switch(Target::Alias(alias)),
+        (None, None, Some(query), None, None) => Action::Switch(Target::Query(query)),
+        (None, None, None, Some(rotate), None) if !rotate.is_empty() => Action::Rotate(rotate),
+        (None, None, None, None, Some(group)) => Action::RotateGroup(group),
+        _ => {
+            return Err(String::from(
+                "exactly one of 'id', 'alias', 'query', 'group' or a non-empty 'rotate' is required",
+            ));
+        }
+    };
+
+    Ok(Rule {
+        name: rule.name,
+        when,
+        action,
+    })
+}
+
+fn parse_days(days: &str) -> Result<Vec<Weekday>, String> {
+    use Weekday::*;
+
+    match days {
+        "daily" => Ok(vec![Mon, Tue, Wed, Thu, Fri, Sat, Sun]),
+        "weekday" => Ok(vec![Mon, Tue, Wed, Thu, Fri]),
+        "weekend" => Ok(vec![Sat, Sun]),
+        list => list
+            .split(',')
+            .map(|day| {
+                day.trim()
+                    .parse::<Weekday>()
+                    .map_err(|_| format!("unknown day '{}'", day.trim()))
+            })
+            .collect(),
+    }
+}
+
+/// Parses durations like `45s`, `30m`, `2h` or `1d`.
+fn parse_every(every: &str) -> Result<TimeDelta, String> {
+    let invalid = || format!("'every' must look like 30m, got '{}'", every);
+    let (split, _) = every.char_indices().last().ok_or_else(invalid)?;
+    let (number, unit) = every.split_at(split);
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+
+    let delta = match unit {
+        "s" => TimeDelta::try_seconds(number),
+        "m" => TimeDelta::try_minutes(number),
+        "h" => TimeDelta::try_hours(number),
+        "d" => TimeDelta::try_days(number),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    if delta < TimeDelta::minutes(1) {
+        return Err(String::from("'every' must be at least 1m"));
+    }
+
+    Ok(delta)
+}
+
+impl Rule {
+    fn is_due(&self, now: DateTime<Local>, last_run: Option<DateTime<Local>>) -> bool {
+        match &self.when {
+            When::Every(every) => last_run.is_none_or(|last| now - last >= *every),
+            When::At { time, days } => {
+                // Most recent scheduled time that is not in the future.
+                let latest = (0..8)
+                    .filter_map(|back| now.date_naive().checked_sub_days(Days::new(back)))
+                    .filter(|date| days.contains(&date.weekday()))
+                    .filter_map(|date| date.and_time(*time).and_local_timezone(Local).earliest())
+                    .find(|at| *at <= now);
+
+                match latest {
+                    Some(at) => now - at < GRACE && last_run.is_none_or(|last| last < at),
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+/// Runs the schedule. Given a pipeline URL, also follows in-game avatar changes there,
+/// and fails if the listener stops.
+pub async fn run_daemon(
+    config: Configuration,
+    schedule: Schedule,
+    pipeline_url: Option<url::Url>,
+) -> Result<(), String> {
+    info!("Daemon started with {} rule(s)", schedule.rules.len());
+
+    let mut changes = None;
+    if let Some(url) = pipeline_url {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(pipeline::listen(config.clone(), url, tx));
+        changes = Some(rx);
+    }
+
+    let mut ticker = interval(TICK);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                for rule in &schedule.rules {
+                    run_rule_if_due(&config, rule).await;
+                }
+            }
+            change = next_change(&mut changes) => {
+                // The listener only stops by panicking; carrying on would quietly stop enforcing.
+                let Some(avatar_id) = change else {
+                    return Err(String::from("The pipeline listener stopped unexpectedly"));
+                };
+                hooks::post_switch(&avatar_id, "in-game", "pipeline").await;
+                if let Some(enforce) = &schedule.enforce {
+                    enforce_avatar(&config, enforce, &avatar_id).await;
+                }
+            }
+            _ = &mut ctrl_c => {
+                info!("Daemon stopped");
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn next_change(changes: &mut Option<mpsc::UnboundedReceiver<String>>) -> Option<String> {
+    match changes {
+        Some(changes) => changes.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Switches back to the enforced avatar after an in-game change to anything else.
+async fn enforce_avatar(config: &Configuration, enforce: &str, avatar_id: &str) {
+    let target = Target::alias_or_id(enforce);
+    let enforced = match resolve_target(&target) {
+        Ok(enforced) => enforced,
+        Err(e) => {
+            warn!("Could not resolve the enforced avatar: {}", e);
+            return;
+        }
+    };
+    if enforced == avatar_id {
+        return;
+    }
+
+    info!("Reverting to the enforced avatar {}", enforced);
+    let input = format!("enforce: {}", target.input());
+    let record = switch_and_record(config.clone(), &enforced, "enforce", &input).await;
+    if !record.success {
+        warn!(
+            "Failed to revert to the enforced avatar: {}",
+            record.error.unwrap_or_default()
+        );
+    }
+}
+
+async fn run_rule_if_due(config: &Configuration, rule: &Rule) {
+    let state = match db::get_daemon_state(&rule.name) {
+        Ok(state) => state,
+        Err(e) => {
+            error!("Failed to read state of rule '{}': {}", rule.name, e);
+            return;
+        }
+    };
+    let (last_run, position) = match state {
+        Some((last_run, position)) => (
+            DateTime::parse_from_rfc3339(&last_run)
+                .ok()
+                .map(|t| t.with_timezone(&Local)),
+            position,
+        ),
+        None => (None, 0),
+    };
+
+    let now = Local::now();
+    if !rule.is_due(now, last_run) {
+        return;
+    }
+
+    let target = match &rule.action {
+        Action::Switch(target) => target.clone(),
+        Action::Rotate(names) => Target::alias_or_id(&names[position % names.len()]),
+        Action::RotateGroup(group) => match db::get_group_aliases(group) {
+            Ok(aliases) if !aliases.is_empty() => {
+                Target::Alias(aliases[position % aliases.len()].clone())
+            }
+            Ok(_) => {
+                warn!("Rule '{}': group '{}' has no aliases", rule.name, group);
+                return;
+            }
+            Err(e) => {
+                error!(
+                    "Rule '{}': failed to read group '{}': {}",
+                    rule.name, group, e
+                );
+                return;
+            }
+        },
+    };
+    info!(
+        "Rule '{}' fired, switching to {}",
+        rule.name,
+        target.input()
+    );
+
+    // Record the run before switching so a crash or restart does not fire it twice.
+    let now_text = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+    if let Err(e) = db::set_daemon_state(&rule.name, &now_text, position + 1) {
+        error!("Failed to save state of rule '{}': {}", rule.name, e);
+        return;
+    }
+
+    let input = format!("{}: {}", rule.name, target.input());
+    match resolve_target(&target) {
+        Ok(avatar_id) => {
+            let record = switch_and_record(config.clone(), &avatar_id, "schedule", &input).await;
+            if record.success {
+                info!("Rule '{}' switched to {}", rule.name, avatar_id);
+            } else {
+                warn!(
+                    "Rule '{}' failed to switch: {}",
+                    rule.name,
+                    record.error.unwrap_or_default()
+                );
+            }
+        }
+        Err(e) => warn!("Rule '{}' could not resolve its avatar: {}", rule.name, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    use crate::test_support;
+
+    fn local(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        // 2025-06-02 is a Monday.
+        Local
+            .with_ymd_and_hms(2025, 6, day, hour, minute, 0)
+            .single()
+            .unwrap()
+    }
+
+    fn rule(when: When) -> Rule {
+        Rule {
+            name: String::from("test"),
+            when,
+            action: Action::Switch(Target::Id(String::from("avtr_test"))),
+        }
+    }
+
+    #[test]
+    fn parse_every_accepts_each_unit() {
+        assert_eq!(
+            parse_every("45s"),
+            Err(String::from("'every' must be at least 1m"))
+        );
+        assert_eq!(parse_every("90s"), Ok(TimeDelta::seconds(90)));
+        assert_eq!(parse_every("30m"), Ok(TimeDelta::minutes(30)));
+        assert_eq!(parse_every("2h"), Ok(TimeDelta::hours(2)));
+        assert_eq!(parse_every("1d"), Ok(TimeDelta::days(1)));
+    }
+
+    #[test]
+    fn parse_every_rejects_bad_input_without_panicking() {
+        for every in [
+            "",
+            "m",
+            "30",
+            "30x",
+            "-",
+            "5é",
+            "é",
+            "9999999999999999d",
+            "x5m",
+        ] {
+            assert_eq!(
+                parse_every(every),
+                Err(format!("'every' must look like 30m, got '{}'", every)),
+                "input {:?}",
+                every
+            );
+        }
+    }
+
+    #[test]
+    fn parse_days_understands_names_and_lists() {
+        use Weekday::*;
+
+        assert_eq!(parse_days("daily").unwrap().len(), 7);
+        assert_eq!(parse_days("weekday"), Ok(vec![Mon, Tue, Wed, Thu, Fri]));
+        assert_eq!(parse_days("weekend"), Ok(vec![Sat, Sun]));
+        assert_eq!(parse_days("mon, wed,Fri"), Ok(vec![Mon, Wed, Fri]));
+        assert_eq!(
+            parse_days("mon,funday"),
+            Err(String::from("unknown day 'funday'"))
+        );
+    }
+
+    #[test]
+    fn every_rule_is_due_once_the_interval_has_passed() {
+        let rule = rule(When::Every(TimeDelta::minutes(30)));
+        let now = local(2, 12, 0);
+
+        assert!(rule.is_due(now, None));
+        assert!(!rule.is_due(now, Some(local(2, 11, 31))));
+        assert!(rule.is_due(now, Some(local(2, 11, 30))));
+    }
+
+    #[test]
+    fn at_rule_is_due_within_the_grace_period_on_its_days() {
+        let rule = rule(When::At {
+            time: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            days: vec![Weekday::Mon],
+        });
+
+        // Monday: due at 09:00 and a little after, not before or long after.
+        assert!(!rule.is_due(local(2, 8, 59), None));
+        assert!(rule.is_due(local(2, 9, 0), None));
+        assert!(rule.is_due(local(2, 9, 4), None));
+        assert!(!rule.is_due(local(2, 9, 5), None));
+        // Not twice for the same 09:00, but again the next Monday.
+        assert!(!rule.is_due(local(2, 9, 2), Some(local(2, 9, 0))));
+        assert!(rule.is_due(local(9, 9, 1), Some(local(2, 9, 0))));
+        // Tuesday is not one of its days.
+        assert!(!rule.is_due(local(3, 9, 0), None));
+    }
+
+    #[test]
+    fn load_schedule_rejects_duplicate_rule_names() {
+        test_support::setup();
+        let path = std::env::current_dir()
+            .unwrap()
+            .join("duplicate-schedule.toml");
+        std::fs::write(
+            &path,
+            "[[rule]]\nname = \"a\"\nevery = \"1h\"\nid = \"avtr_1\"\n\n\
+             [[rule]]\nname = \"a\"\nat = \"09:00\"\nid = \"avtr_2\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            load_schedule(&path).unwrap_err(),
+            "Rule 'a' is defined more than once"
+        );
+    }
+}
@@ -1,8 +1,97 @@
-use rusqlite::Connection;
-use vrchatapi::models::Avatar;
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{Connection, Row, params};
+use serde::Serialize;
+use std::fmt;
+use tracing::{info, warn};
+use vrchatapi::models::{Avatar, Favorite, FavoriteGroup, UnityPackage};
 
-pub fn create_avatar_db() -> Result<(), rusqlite::Error> {
+use crate::output::Record;
+
+const AVATAR_COLUMNS: &str = "id, name, description, version, thumbnail_image_url, created_at, updated_at, platforms, author_name";
+
+pub fn open() -> Result<Connection, rusqlite::Error> {
     let conn = Connection::open("./avatars.db")?;
+    migrate(&conn)?;
+
+    Ok(conn)
+}
+
+fn migrate(conn: &Connection) -> Result<(), rusqlite::Error> {
+    // Databases built before platforms were recorded lack the column.
+    add_missing_column(conn, "avatars", "platforms", "TEXT")?;
+    add_missing_column(conn, "avatars", "author_name", "TEXT")?;
+    add_missing_column(conn, "avatars", "origin", "TEXT")?;
+    // Likewise for aliases made before they could carry a description, avatar name or selector.
+    add_missing_column(conn, "aliases", "description", "TEXT")?;
+    add_missing_column(conn, "aliases", "avatar_name", "TEXT")?;
+    add_missing_column(conn, "aliases", "match_name", "TEXT")?;
+    add_missing_column(conn, "aliases", "match_author", "TEXT")?;
+    add_missing_column(conn, "aliases", "match_query", "TEXT")?;
+
+    Ok(())
+}
+
+/// Adds `column` to `table` if the table exists without it.
+fn add_missing_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let columns = stmt
+        .query_map([table], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if !columns.is_empty() && !columns.iter().any(|c| c == column) {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn avatar_from_row(row: &Row) -> Result<Avatar, rusqlite::Error> {
+    let platforms: Option<String> = row.get(7)?;
+
+    Ok(Avatar {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        version: row.get(3)?,
+        thumbnail_image_url: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        author_name: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+        unity_packages: platforms
+            .unwrap_or_default()
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(|p| UnityPackage {
+                platform: p.to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// Distinct platforms an avatar has been built for, in upload order.
+pub fn avatar_platforms(avatar: &Avatar) -> Vec<String> {
+    let mut platforms: Vec<String> = Vec::new();
+    for package in &avatar.unity_packages {
+        if !platforms.contains(&package.platform) {
+            platforms.push(package.platform.clone());
+        }
+    }
+
+    platforms
+}
+
+pub fn create_avatar_db() -> Result<(), rusqlite::Error> {
+    let conn = open()?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS avatars (
@@ -12,7 +101,10 @@ pub fn create_avatar_db() -> Result<(), rusqlite::Error> {
         version INTEGER,
         thumbnail_image_url TEXT,
         created_at TEXT,
-        updated_at TEXT
+        updated_at TEXT,
+        platforms TEXT,
+        author_name TEXT,
+        origin TEXT
       )",
         [],
     )?;
@@ -21,12 +113,17 @@ pub fn create_avatar_db() -> Result<(), rusqlite::Error> {
 }
 
 pub fn create_alias_db() -> Result<(), rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+    let conn = open()?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS aliases (
       name TEXT PRIMARY KEY,
-      avatar_id TEXT NOT NULL
+      avatar_id TEXT NOT NULL,
+      description TEXT,
+      avatar_name TEXT,
+      match_name TEXT,
+      match_author TEXT,
+      match_query TEXT
     )",
         [],
     )?;
@@ -34,24 +131,62 @@ pub fn create_alias_db() -> Result<(), rusqlite::Error> {
     Ok(())
 }
 
-pub fn rebuild_avatar_db(avatars: Vec<Avatar>) -> Result<(), rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+/// Where an avatar in the local database came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Uploaded by us.
+    Own,
+    /// Someone else's avatar in one of our favourite groups.
+    Favorite,
+    /// Looked up by ID because we used it somewhere, e.g. in an alias or a switch.
+    External,
+}
+
+impl Origin {
+    fn as_str(self) -> &'static str {
+        match self {
+            Origin::Own => "own",
+            Origin::Favorite => "favorite",
+            Origin::External => "external",
+        }
+    }
+}
 
-    conn.execute("DROP TABLE IF EXISTS avatars", [])?;
+/// Replaces the avatars of one origin, leaving the others alone. Our own avatars
+/// win over favourites when an avatar is both.
+pub fn rebuild_avatar_db(avatars: Vec<Avatar>, origin: Origin) -> Result<(), rusqlite::Error> {
     create_avatar_db()?;
-    insert_avatars(avatars)?;
+    let conn = open()?;
 
-    println!("Rebuilt avatar database.");
+    // Rows from before origins were recorded are all our own uploads.
+    conn.execute(
+        "DELETE FROM avatars WHERE COALESCE(origin, 'own') = ?1",
+        [origin.as_str()],
+    )?;
+    insert_avatars(avatars, origin)?;
+
+    info!("Rebuilt avatar database ({} avatars).", origin.as_str());
     Ok(())
 }
 
-fn insert_avatars(avatars: Vec<Avatar>) -> Result<(), rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+/// Stores a single avatar, e.g. after editing it or looking it up by ID.
+pub fn save_avatar(avatar: Avatar, origin: Origin) -> Result<(), rusqlite::Error> {
+    create_avatar_db()?;
+    insert_avatars(vec![avatar], origin)
+}
+
+fn insert_avatars(avatars: Vec<Avatar>, origin: Origin) -> Result<(), rusqlite::Error> {
+    let conn = open()?;
+    let conflict = match origin {
+        Origin::Own => "REPLACE",
+        Origin::Favorite | Origin::External => "IGNORE",
+    };
+    let origin = origin.as_str().to_string();
 
     for avatar in avatars {
         if let Err(e) = conn.execute(
-      "INSERT INTO avatars (id, name, description, version, thumbnail_image_url, created_at, updated_at)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+      &format!("INSERT OR {} INTO avatars (id, name, description, version, thumbnail_image_url, created_at, updated_at, platforms, author_name, origin)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)", conflict),
           [
             &avatar.id,
             &avatar.name,
@@ -60,8 +195,11 @@ fn insert_avatars(avatars: Vec<Avatar>) -> Result<(), rusqlite::Error> {
             &avatar.thumbnail_image_url,
             &avatar.created_at,
             &avatar.updated_at,
+            &avatar_platforms(&avatar).join(","),
+            &avatar.author_name,
+            &origin,
         ]) {
-          eprintln!("Failed to insert avatar {}: {}", avatar.id, e);
+          warn!("Failed to insert avatar {}: {}", avatar.id, e);
         };
     }
 
@@ -69,21 +207,10 @@ fn insert_avatars(avatars: Vec<Avatar>) -> Result<(), rusqlite::Error> {
 }
 
 pub fn get_all_avatars() -> Result<Vec<Avatar>, rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+    let conn = open()?;
 
-    let mut stmt = conn.prepare("SELECT id, name, description, version, thumbnail_image_url, created_at, updated_at FROM avatars")?;
-    let avatar_iter = stmt.query_map([], |row| {
-        Ok(Avatar {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            description: row.get(2)?,
-            version: row.get(3)?,
-            thumbnail_image_url: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-            ..Default::default()
-        })
-    })?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM avatars", AVATAR_COLUMNS))?;
+    let avatar_iter = stmt.query_map([], avatar_from_row)?;
 
     let mut avatars = Vec::new();
     for avatar in avatar_iter {
@@ -93,44 +220,63 @@ pub fn get_all_avatars() -> Result<Vec<Avatar>, rusqlite::Error> {
     Ok(avatars)
 }
 
+/// IDs of the avatars we uploaded ourselves, as opposed to favourites.
+pub fn get_own_avatar_ids() -> Result<Vec<String>, rusqlite::Error> {
+    create_avatar_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare("SELECT id FROM avatars WHERE COALESCE(origin, 'own') = 'own'")?;
+    let id_iter = stmt.query_map([], |row| row.get(0))?;
+
+    let mut ids = Vec::new();
+    for id in id_iter {
+        ids.push(id?);
+    }
+
+    Ok(ids)
+}
+
 pub fn get_avatar_first_hit_by_name(query: &str) -> Result<Option<Avatar>, rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+    let conn = open()?;
 
-    let mut stmt = conn.prepare("SELECT id, name, description, version, thumbnail_image_url, created_at, updated_at FROM avatars WHERE name LIKE ?1 LIMIT 1")?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM avatars WHERE name LIKE ?1 LIMIT 1",
+        AVATAR_COLUMNS
+    ))?;
     let mut rows = stmt.query([format!("%{}%", query)])?;
 
     if let Some(row) = rows.next()? {
-        Ok(Some(Avatar {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            description: row.get(2)?,
-            version: row.get(3)?,
-            thumbnail_image_url: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-            ..Default::default()
-        }))
+        Ok(Some(avatar_from_row(row)?))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn get_avatar_by_id(avatar_id: &str) -> Result<Option<Avatar>, rusqlite::Error> {
+    create_avatar_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM avatars WHERE id = ?1",
+        AVATAR_COLUMNS
+    ))?;
+    let mut rows = stmt.query([avatar_id])?;
+
+    if let Some(row) = rows.next()? {
+        Ok(Some(avatar_from_row(row)?))
     } else {
         Ok(None)
     }
 }
 
 pub fn get_avatars_by_name(query: &str) -> Result<Vec<Avatar>, rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+    let conn = open()?;
 
-    let mut stmt = conn.prepare("SELECT id, name, description, version, thumbnail_image_url, created_at, updated_at FROM avatars WHERE name LIKE ?1")?;
-    let avatar_iter = stmt.query_map([format!("%{}%", query)], |row| {
-        Ok(Avatar {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            description: row.get(2)?,
-            version: row.get(3)?,
-            thumbnail_image_url: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-            ..Default::default()
-        })
-    })?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM avatars WHERE name LIKE ?1",
+        AVATAR_COLUMNS
+    ))?;
+    let avatar_iter = stmt.query_map([format!("%{}%", query)], avatar_from_row)?;
 
     let mut avatars = Vec::new();
     for avatar in avatar_iter {
@@ -140,57 +286,292 @@ pub fn get_avatars_by_name(query: &str) -> Result<Vec<Avatar>, rusqlite::Error>
     Ok(avatars)
 }
 
-pub fn register_alias(alias: &str, avatar_id: &str) -> Result<(), rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+/// Avatars a selector currently matches.
+pub fn get_avatars_by_selector(selector: &Selector) -> Result<Vec<Avatar>, rusqlite::Error> {
+    let (name, author) = match selector {
+        Selector::Name { name, author } => (name, author),
+        Selector::Query(query) => return get_avatars_by_name(query),
+    };
+    create_avatar_db()?;
+    let conn = open()?;
 
-    let result = conn.execute(
-        "INSERT INTO aliases (name, avatar_id) VALUES (?1, ?2)
-         ON CONFLICT(name) DO NOTHING",
-        [alias, avatar_id],
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM avatars WHERE name = ?1 AND (?2 IS NULL OR author_name = ?2)",
+        AVATAR_COLUMNS
+    ))?;
+    let avatar_iter = stmt.query_map(params![name, author], avatar_from_row)?;
 
-    if result == 0 {
-        println!("Alias '{}' already exists. No changes made.", alias);
-    } else {
-        println!("Made new alias {} -> {}", alias, avatar_id);
+    let mut avatars = Vec::new();
+    for avatar in avatar_iter {
+        avatars.push(avatar?);
     }
 
-    Ok(())
+    Ok(avatars)
 }
 
-pub fn remove_alias(alias: &str) -> Result<(), rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+#[derive(Debug, Clone)]
+pub struct Alias {
+    pub name: String,
+    pub avatar_id: String,
+    pub description: Option<String>,
+    /// Name of the avatar when the alias was last set or checked, so it can be
+    /// found again if its ID goes away.
+    pub avatar_name: Option<String>,
+    /// When set, the alias is re-resolved through this at switch time and
+    /// `avatar_id` only caches the last match.
+    pub selector: Option<Selector>,
+}
+
+/// A stable way of naming an avatar that survives re-uploads under a new ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    /// Exact avatar name, optionally only from one author.
+    Name {
+        name: String,
+        author: Option<String>,
+    },
+    /// Saved local search query.
+    Query(String),
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Selector::Name { name, author: None } => write!(f, "name '{}'", name),
+            Selector::Name {
+                name,
+                author: Some(author),
+            } => write!(f, "name '{}' by '{}'", name, author),
+            Selector::Query(query) => write!(f, "query '{}'", query),
+        }
+    }
+}
+
+impl Selector {
+    /// Values for the `match_name`, `match_author` and `match_query` columns.
+    fn columns(selector: Option<&Selector>) -> (Option<&str>, Option<&str>, Option<&str>) {
+        match selector {
+            Some(Selector::Name { name, author }) => (Some(name), author.as_deref(), None),
+            Some(Selector::Query(query)) => (None, None, Some(query)),
+            None => (None, None, None),
+        }
+    }
+}
+
+const ALIAS_COLUMNS: &str =
+    "name, avatar_id, description, avatar_name, match_name, match_author, match_query";
+
+fn alias_from_row(row: &Row) -> Result<Alias, rusqlite::Error> {
+    let match_name: Option<String> = row.get(4)?;
+    let match_author: Option<String> = row.get(5)?;
+    let match_query: Option<String> = row.get(6)?;
+
+    Ok(Alias {
+        name: row.get(0)?,
+        avatar_id: row.get(1)?,
+        description: row.get(2)?,
+        avatar_name: row.get(3)?,
+        selector: match (match_name, match_query) {
+            (Some(name), _) => Some(Selector::Name {
+                name,
+                author: match_author,
+            }),
+            (None, Some(query)) => Some(Selector::Query(query)),
+            (None, None) => None,
+        },
+    })
+}
+
+/// What an alias command did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AliasChange {
+    Created,
+    Updated,
+    Unchanged,
+    /// The alias already points elsewhere and `force` was not given.
+    Exists,
+    Renamed,
+    Removed,
+    NotFound,
+}
+
+/// Points `alias` at `avatar_id`, or at whatever `selector` matches if one is given
+/// (`avatar_id` is then its current match). An alias pointing at another avatar or
+/// selector is only overwritten with `force`; a `None` description keeps the current one.
+pub fn register_alias(
+    alias: &str,
+    avatar_id: &str,
+    selector: Option<&Selector>,
+    description: Option<&str>,
+    force: bool,
+) -> Result<AliasChange, rusqlite::Error> {
+    register_alias_in(&open()?, alias, avatar_id, selector, description, force)
+}
+
+/// `register_alias` on a given connection, so several changes can share a transaction.
+pub fn register_alias_in(
+    conn: &Connection,
+    alias: &str,
+    avatar_id: &str,
+    selector: Option<&Selector>,
+    description: Option<&str>,
+    force: bool,
+) -> Result<AliasChange, rusqlite::Error> {
+    let avatar_name = get_avatar_by_id(avatar_id)?.map(|avatar| avatar.name);
+    let (match_name, match_author, match_query) = Selector::columns(selector);
+
+    let change = match get_alias_in(conn, alias)? {
+        None => {
+            conn.execute(
+                "INSERT INTO aliases
+                 (name, avatar_id, description, avatar_name, match_name, match_author, match_query)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    alias,
+                    avatar_id,
+                    description,
+                    avatar_name,
+                    match_name,
+                    match_author,
+                    match_query
+                ],
+            )?;
+            AliasChange::Created
+        }
+        Some(existing)
+            if (existing.avatar_id != avatar_id || existing.selector.as_ref() != selector)
+                && !force =>
+        {
+            AliasChange::Exists
+        }
+        Some(existing)
+            if existing.avatar_id == avatar_id
+                && existing.selector.as_ref() == selector
+                && description.is_none_or(|d| existing.description.as_deref() == Some(d)) =>
+        {
+            AliasChange::Unchanged
+        }
+        Some(_) => {
+            conn.execute(
+                "UPDATE aliases
+                 SET avatar_id = ?2, description = COALESCE(?3, description), avatar_name = ?4,
+                     match_name = ?5, match_author = ?6, match_query = ?7
+                 WHERE name = ?1",
+                params![
+                    alias,
+                    avatar_id,
+                    description,
+                    avatar_name,
+                    match_name,
+                    match_author,
+                    match_query
+                ],
+            )?;
+            AliasChange::Updated
+        }
+    };
+
+    Ok(change)
+}
+
+/// Renames an alias, carrying its group memberships along. An existing alias
+/// named `new` is only replaced with `force`.
+pub fn rename_alias(old: &str, new: &str, force: bool) -> Result<AliasChange, rusqlite::Error> {
+    create_group_db()?;
+    let mut conn = open()?;
+
+    if get_alias(old)?.is_none() {
+        return Ok(AliasChange::NotFound);
+    }
+    if old == new {
+        return Ok(AliasChange::Unchanged);
+    }
+    if get_alias(new)?.is_some() && !force {
+        return Ok(AliasChange::Exists);
+    }
+
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM aliases WHERE name = ?1", [new])?;
+    tx.execute("UPDATE aliases SET name = ?2 WHERE name = ?1", [old, new])?;
+    tx.execute(
+        "UPDATE OR IGNORE alias_groups SET alias = ?2 WHERE alias = ?1",
+        [old, new],
+    )?;
+    tx.execute("DELETE FROM alias_groups WHERE alias = ?1", [old])?;
+    tx.commit()?;
+
+    Ok(AliasChange::Renamed)
+}
 
+pub fn remove_alias(alias: &str) -> Result<AliasChange, rusqlite::Error> {
+    remove_alias_in(&open()?, alias)
+}
+
+pub fn remove_alias_in(conn: &Connection, alias: &str) -> Result<AliasChange, rusqlite::Error> {
     let result = conn.execute("DELETE FROM aliases WHERE name = ?1", [alias])?;
 
     if result == 0 {
-        println!("Alias '{}' does not exist. No changes made.", alias);
+        Ok(AliasChange::NotFound)
     } else {
-        println!("Removed alias '{}'.", alias);
+        Ok(AliasChange::Removed)
     }
-
-    Ok(())
 }
 
-pub fn get_avatar_id_by_alias(alias: &str) -> Result<String, rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+pub fn get_alias(alias: &str) -> Result<Option<Alias>, rusqlite::Error> {
+    get_alias_in(&open()?, alias)
+}
 
-    let mut stmt = conn.prepare("SELECT avatar_id FROM aliases WHERE name = ?1")?;
+fn get_alias_in(conn: &Connection, alias: &str) -> Result<Option<Alias>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM aliases WHERE name = ?1",
+        ALIAS_COLUMNS
+    ))?;
     let mut rows = stmt.query([alias])?;
 
     if let Some(row) = rows.next()? {
-        let avatar_id: String = row.get(0)?;
-        Ok(avatar_id)
+        Ok(Some(alias_from_row(row)?))
     } else {
-        Err(rusqlite::Error::QueryReturnedNoRows)
+        Ok(None)
     }
 }
 
-pub fn get_all_aliases() -> Result<Vec<(String, String)>, rusqlite::Error> {
-    let conn = Connection::open("./avatars.db")?;
+/// Records the current name of the avatar an alias points at.
+pub fn set_alias_avatar_name(alias: &str, avatar_name: &str) -> Result<(), rusqlite::Error> {
+    let conn = open()?;
+
+    conn.execute(
+        "UPDATE aliases SET avatar_name = ?2 WHERE name = ?1",
+        [alias, avatar_name],
+    )?;
+
+    Ok(())
+}
+
+/// Caches the avatar a selector alias matched, keeping its selector.
+pub fn set_alias_match(
+    alias: &str,
+    avatar_id: &str,
+    avatar_name: &str,
+) -> Result<(), rusqlite::Error> {
+    let conn = open()?;
+
+    conn.execute(
+        "UPDATE aliases SET avatar_id = ?2, avatar_name = ?3 WHERE name = ?1",
+        [alias, avatar_id, avatar_name],
+    )?;
 
-    let mut stmt = conn.prepare("SELECT name, avatar_id FROM aliases")?;
-    let alias_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(())
+}
+
+pub fn get_all_aliases() -> Result<Vec<Alias>, rusqlite::Error> {
+    let conn = open()?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM aliases ORDER BY name",
+        ALIAS_COLUMNS
+    ))?;
+    let alias_iter = stmt.query_map([], alias_from_row)?;
 
     let mut aliases = Vec::new();
     for alias in alias_iter {
@@ -199,3 +580,670 @@ pub fn get_all_aliases() -> Result<Vec<(String, String)>, rusqlite::Error> {
 
     Ok(aliases)
 }
+
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    pub switched_at: String,
+    pub avatar_id: String,
+    pub avatar_name: Option<String>,
+    pub resolved_by: String,
+    pub input: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl Record for HistoryEntry {
+    const COLUMNS: &'static [&'static str] = &[
+        "switched_at",
+        "avatar_id",
+        "avatar_name",
+        "resolved_by",
+        "input",
+        "success",
+        "error",
+    ];
+}
+
+pub fn create_history_db() -> Result<(), rusqlite::Error> {
+    let conn = open()?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS history (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      switched_at TEXT NOT NULL,
+      avatar_id TEXT NOT NULL,
+      resolved_by TEXT NOT NULL,
+      input TEXT NOT NULL,
+      success INTEGER NOT NULL,
+      error TEXT
+    )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+pub fn record_switch(
+    avatar_id: &str,
+    resolved_by: &str,
+    input: &str,
+    error: Option<&str>,
+) -> Result<(), rusqlite::Error> {
+    create_history_db()?;
+    let conn = open()?;
+
+    conn.execute(
+        "INSERT INTO history (switched_at, avatar_id, resolved_by, input, success, error)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            avatar_id,
+            resolved_by,
+            input,
+            error.is_none(),
+            error
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Most recent switches first.
+pub fn get_history(limit: usize) -> Result<Vec<HistoryEntry>, rusqlite::Error> {
+    create_avatar_db()?;
+    create_history_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT h.switched_at, h.avatar_id, a.name, h.resolved_by, h.input, h.success, h.error
+         FROM history h LEFT JOIN avatars a ON a.id = h.avatar_id
+         ORDER BY h.id DESC LIMIT ?1",
+    )?;
+    let history_iter = stmt.query_map([limit as i64], |row| {
+        Ok(HistoryEntry {
+            switched_at: row.get(0)?,
+            avatar_id: row.get(1)?,
+            avatar_name: row.get(2)?,
+            resolved_by: row.get(3)?,
+            input: row.get(4)?,
+            success: row.get(5)?,
+            error: row.get(6)?,
+        })
+    })?;
+
+    let mut history = Vec::new();
+    for entry in history_iter {
+        history.push(entry?);
+    }
+
+    Ok(history)
+}
+
+/// The avatar worn `steps` successful switches ago, ignoring repeats of the same avatar.
+pub fn get_previous_avatar_id(steps: usize) -> Result<Option<String>, rusqlite::Error> {
+    create_history_db()?;
+    let conn = open()?;
+
+    let mut stmt =
+        conn.prepare("SELECT avatar_id FROM history WHERE success = 1 ORDER BY id DESC")?;
+    let ids = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut worn: Vec<String> = Vec::new();
+    for id in ids {
+        if worn.last() != Some(&id) {
+            worn.push(id);
+        }
+    }
+
+    Ok(worn.get(steps).cloned())
+}
+
+/// Successful switches as `(switched_at, avatar_id)`, oldest first.
+pub fn get_successful_switches() -> Result<Vec<(String, String)>, rusqlite::Error> {
+    create_history_db()?;
+    let conn = open()?;
+
+    let mut stmt =
+        conn.prepare("SELECT switched_at, avatar_id FROM history WHERE success = 1 ORDER BY id")?;
+    let switch_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    let mut switches = Vec::new();
+    for switch in switch_iter {
+        switches.push(switch?);
+    }
+
+    Ok(switches)
+}
+
+/// The last `count` distinct avatars switched to, most recent first.
+pub fn get_recent_avatar_ids(count: usize) -> Result<Vec<String>, rusqlite::Error> {
+    let mut recent: Vec<String> = Vec::new();
+    for (_, avatar_id) in get_successful_switches()?.into_iter().rev() {
+        if recent.len() >= count {
+            break;
+        }
+        if !recent.contains(&avatar_id) {
+            recent.push(avatar_id);
+        }
+    }
+
+    Ok(recent)
+}
+
+pub fn create_daemon_state_db() -> Result<(), rusqlite::Error> {
+    let conn = open()?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS daemon_state (
+      rule TEXT PRIMARY KEY,
+      last_run TEXT NOT NULL,
+      position INTEGER NOT NULL DEFAULT 0
+    )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Last run time (RFC 3339) and rotation position of a schedule rule.
+pub fn get_daemon_state(rule: &str) -> Result<Option<(String, usize)>, rusqlite::Error> {
+    create_daemon_state_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare("SELECT last_run, position FROM daemon_state WHERE rule = ?1")?;
+    let mut rows = stmt.query([rule])?;
+
+    if let Some(row) = rows.next()? {
+        Ok(Some((row.get(0)?, row.get::<_, i64>(1)? as usize)))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn set_daemon_state(
+    rule: &str,
+    last_run: &str,
+    position: usize,
+) -> Result<(), rusqlite::Error> {
+    create_daemon_state_db()?;
+    let conn = open()?;
+
+    conn.execute(
+        "INSERT INTO daemon_state (rule, last_run, position) VALUES (?1, ?2, ?3)
+         ON CONFLICT(rule) DO UPDATE SET last_run = excluded.last_run, position = excluded.position",
+        params![rule, last_run, position as i64],
+    )?;
+
+    Ok(())
+}
+
+pub fn create_tag_db() -> Result<(), rusqlite::Error> {
+    let conn = open()?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS avatar_tags (
+      avatar_id TEXT NOT NULL,
+      tag TEXT NOT NULL,
+      PRIMARY KEY (avatar_id, tag)
+    )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Returns whether the tag was newly added.
+pub fn add_tag(avatar_id: &str, tag: &str) -> Result<bool, rusqlite::Error> {
+    create_tag_db()?;
+    let conn = open()?;
+
+    let result = conn.execute(
+        "INSERT INTO avatar_tags (avatar_id, tag) VALUES (?1, ?2)
+         ON CONFLICT(avatar_id, tag) DO NOTHING",
+        [avatar_id, tag],
+    )?;
+
+    Ok(result > 0)
+}
+
+/// Returns whether the tag was there to remove.
+pub fn remove_tag(avatar_id: &str, tag: &str) -> Result<bool, rusqlite::Error> {
+    create_tag_db()?;
+    let conn = open()?;
+
+    let result = conn.execute(
+        "DELETE FROM avatar_tags WHERE avatar_id = ?1 AND tag = ?2",
+        [avatar_id, tag],
+    )?;
+
+    Ok(result > 0)
+}
+
+/// All `(avatar_id, tag)` pairs, sorted by tag.
+pub fn get_all_tags() -> Result<Vec<(String, String)>, rusqlite::Error> {
+    create_tag_db()?;
+    let conn = open()?;
+
+    let mut stmt =
+        conn.prepare("SELECT avatar_id, tag FROM avatar_tags ORDER BY tag, avatar_id")?;
+    let tag_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    let mut tags = Vec::new();
+    for tag in tag_iter {
+        tags.push(tag?);
+    }
+
+    Ok(tags)
+}
+
+pub fn get_avatar_ids_with_tag(tag: &str) -> Result<Vec<String>, rusqlite::Error> {
+    create_tag_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare("SELECT avatar_id FROM avatar_tags WHERE tag = ?1")?;
+    let id_iter = stmt.query_map([tag], |row| row.get(0))?;
+
+    let mut ids = Vec::new();
+    for id in id_iter {
+        ids.push(id?);
+    }
+
+    Ok(ids)
+}
+
+pub fn create_group_db() -> Result<(), rusqlite::Error> {
+    let conn = open()?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS alias_groups (
+      group_name TEXT NOT NULL,
+      alias TEXT NOT NULL,
+      PRIMARY KEY (group_name, alias)
+    )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Returns whether the alias was newly added to the group.
+pub fn add_to_group(group: &str, alias: &str) -> Result<bool, rusqlite::Error> {
+    create_group_db()?;
+    let conn = open()?;
+
+    let result = conn.execute(
+        "INSERT INTO alias_groups (group_name, alias) VALUES (?1, ?2)
+         ON CONFLICT(group_name, alias) DO NOTHING",
+        [group, alias],
+    )?;
+
+    Ok(result > 0)
+}
+
+/// Returns whether the alias was in the group.
+pub fn remove_from_group(group: &str, alias: &str) -> Result<bool, rusqlite::Error> {
+    create_group_db()?;
+    let conn = open()?;
+
+    let result = conn.execute(
+        "DELETE FROM alias_groups WHERE group_name = ?1 AND alias = ?2",
+        [group, alias],
+    )?;
+
+    Ok(result > 0)
+}
+
+/// Returns how many aliases the group had.
+pub fn delete_group(group: &str) -> Result<usize, rusqlite::Error> {
+    create_group_db()?;
+    let conn = open()?;
+
+    conn.execute("DELETE FROM alias_groups WHERE group_name = ?1", [group])
+}
+
+/// All `(group, alias, avatar_id)` memberships, sorted by group. The avatar ID is
+/// `None` when the alias no longer exists.
+pub fn get_all_group_members() -> Result<Vec<(String, String, Option<String>)>, rusqlite::Error> {
+    create_alias_db()?;
+    create_group_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT g.group_name, g.alias, a.avatar_id
+         FROM alias_groups g LEFT JOIN aliases a ON a.name = g.alias
+         ORDER BY g.group_name, g.alias",
+    )?;
+    let member_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+
+    let mut members = Vec::new();
+    for member in member_iter {
+        members.push(member?);
+    }
+
+    Ok(members)
+}
+
+/// Aliases in a group, in name order.
+pub fn get_group_aliases(group: &str) -> Result<Vec<String>, rusqlite::Error> {
+    Ok(get_all_group_members()?
+        .into_iter()
+        .filter(|(name, _, _)| name == group)
+        .map(|(_, alias, _)| alias)
+        .collect())
+}
+
+pub fn create_favorite_db() -> Result<(), rusqlite::Error> {
+    let conn = open()?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS favorite_groups (
+      name TEXT PRIMARY KEY,
+      display_name TEXT NOT NULL,
+      visibility TEXT
+    )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS favorites (
+      favorite_id TEXT NOT NULL,
+      avatar_id TEXT NOT NULL,
+      group_name TEXT NOT NULL,
+      PRIMARY KEY (avatar_id, group_name)
+    )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Replaces the local copy of our avatar favourite groups and their members.
+pub fn replace_favorites(
+    groups: &[FavoriteGroup],
+    favorites: &[Favorite],
+) -> Result<(), rusqlite::Error> {
+    create_favorite_db()?;
+    let mut conn = open()?;
+
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM favorite_groups", [])?;
+    tx.execute("DELETE FROM favorites", [])?;
+    for group in groups {
+        tx.execute(
+            "INSERT INTO favorite_groups (name, display_name, visibility) VALUES (?1, ?2, ?3)",
+            [
+                &group.name,
+                &group.display_name,
+                &group.visibility.to_string(),
+            ],
+        )?;
+    }
+    for favorite in favorites {
+        // A favourite's tags are the names of the groups it is in.
+        for group in &favorite.tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO favorites (favorite_id, avatar_id, group_name)
+                 VALUES (?1, ?2, ?3)",
+                [&favorite.id, &favorite.favorite_id, group],
+            )?;
+        }
+    }
+    tx.commit()?;
+
+    info!(
+        "Stored {} favourite groups with {} favourites.",
+        groups.len(),
+        favorites.len()
+    );
+    Ok(())
+}
+
+/// IDs of avatars in any of our favourite groups.
+pub fn get_favorited_avatar_ids() -> Result<Vec<String>, rusqlite::Error> {
+    create_favorite_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare("SELECT DISTINCT avatar_id FROM favorites")?;
+    let id_iter = stmt.query_map([], |row| row.get(0))?;
+
+    let mut ids = Vec::new();
+    for id in id_iter {
+        ids.push(id?);
+    }
+
+    Ok(ids)
+}
+
+#[derive(Debug, Clone)]
+pub struct FavoriteGroupEntry {
+    pub name: String,
+    pub display_name: String,
+    pub visibility: Option<String>,
+    pub avatars: usize,
+}
+
+/// Favourite groups in name order.
+pub fn get_favorite_groups() -> Result<Vec<FavoriteGroupEntry>, rusqlite::Error> {
+    create_favorite_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT g.name, g.display_name, g.visibility, COUNT(f.avatar_id)
+         FROM favorite_groups g LEFT JOIN favorites f ON f.group_name = g.name
+         GROUP BY g.name ORDER BY g.name",
+    )?;
+    let group_iter = stmt.query_map([], |row| {
+        Ok(FavoriteGroupEntry {
+            name: row.get(0)?,
+            display_name: row.get(1)?,
+            visibility: row.get(2)?,
+            avatars: row.get(3)?,
+        })
+    })?;
+
+    let mut groups = Vec::new();
+    for group in group_iter {
+        groups.push(group?);
+    }
+
+    Ok(groups)
+}
+
+/// Finds a favourite group by its name (`avatars1`) or display name.
+pub fn find_favorite_group(name: &str) -> Result<Option<String>, rusqlite::Error> {
+    create_favorite_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT name FROM favorite_groups WHERE name = ?1 OR display_name = ?1
+         ORDER BY name = ?1 DESC LIMIT 1",
+    )?;
+    let mut rows = stmt.query([name])?;
+
+    if let Some(row) = rows.next()? {
+        Ok(Some(row.get(0)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Favourites as `(group, avatar_id, avatar_name)`, optionally only one group's.
+pub fn get_favorites(
+    group: Option<&str>,
+) -> Result<Vec<(String, String, Option<String>)>, rusqlite::Error> {
+    create_avatar_db()?;
+    create_favorite_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT f.group_name, f.avatar_id, a.name
+         FROM favorites f LEFT JOIN avatars a ON a.id = f.avatar_id
+         WHERE ?1 IS NULL OR f.group_name = ?1
+         ORDER BY f.group_name, a.name",
+    )?;
+    let favorite_iter =
+        stmt.query_map([group], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+
+    let mut favorites = Vec::new();
+    for favorite in favorite_iter {
+        favorites.push(favorite?);
+    }
+
+    Ok(favorites)
+}
+
+/// The favourite (`fvrt_...`) ID under which an avatar is favourited.
+pub fn get_favorite_id(avatar_id: &str) -> Result<Option<String>, rusqlite::Error> {
+    create_favorite_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare("SELECT favorite_id FROM favorites WHERE avatar_id = ?1")?;
+    let mut rows = stmt.query([avatar_id])?;
+
+    if let Some(row) = rows.next()? {
+        Ok(Some(row.get(0)?))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn add_favorite(favorite: &Favorite) -> Result<(), rusqlite::Error> {
+    create_favorite_db()?;
+    let conn = open()?;
+
+    for group in &favorite.tags {
+        conn.execute(
+            "INSERT OR REPLACE INTO favorites (favorite_id, avatar_id, group_name)
+             VALUES (?1, ?2, ?3)",
+            [&favorite.id, &favorite.favorite_id, group],
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn remove_favorite(favorite_id: &str) -> Result<(), rusqlite::Error> {
+    create_favorite_db()?;
+    let conn = open()?;
+
+    conn.execute(
+        "DELETE FROM favorites WHERE favorite_id = ?1",
+        [favorite_id],
+    )?;
+
+    Ok(())
+}
+
+pub fn rename_favorite_group(name: &str, display_name: &str) -> Result<(), rusqlite::Error> {
+    create_favorite_db()?;
+    let conn = open()?;
+
+    conn.execute(
+        "UPDATE favorite_groups SET display_name = ?2 WHERE name = ?1",
+        [name, display_name],
+    )?;
+
+    Ok(())
+}
+
+pub fn create_preset_db() -> Result<(), rusqlite::Error> {
+    let conn = open()?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS presets (
+      name TEXT PRIMARY KEY,
+      avatar TEXT,
+      status TEXT,
+      status_description TEXT,
+      bio TEXT
+    )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// An avatar and profile to put on together. The avatar is an alias or avatar ID,
+/// resolved when the preset is applied.
+#[derive(Debug, Clone)]
+pub struct Preset {
+    pub name: String,
+    pub avatar: Option<String>,
+    pub status: Option<String>,
+    pub status_description: Option<String>,
+    pub bio: Option<String>,
+}
+
+fn preset_from_row(row: &Row) -> Result<Preset, rusqlite::Error> {
+    Ok(Preset {
+        name: row.get(0)?,
+        avatar: row.get(1)?,
+        status: row.get(2)?,
+        status_description: row.get(3)?,
+        bio: row.get(4)?,
+    })
+}
+
+/// Stores a preset, replacing one of the same name.
+pub fn save_preset(preset: &Preset) -> Result<(), rusqlite::Error> {
+    create_preset_db()?;
+    let conn = open()?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO presets (name, avatar, status, status_description, bio)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            preset.name,
+            preset.avatar,
+            preset.status,
+            preset.status_description,
+            preset.bio
+        ],
+    )?;
+
+    Ok(())
+}
+
+pub fn get_preset(name: &str) -> Result<Option<Preset>, rusqlite::Error> {
+    create_preset_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT name, avatar, status, status_description, bio FROM presets WHERE name = ?1",
+    )?;
+    let mut rows = stmt.query([name])?;
+
+    if let Some(row) = rows.next()? {
+        Ok(Some(preset_from_row(row)?))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn get_all_presets() -> Result<Vec<Preset>, rusqlite::Error> {
+    create_preset_db()?;
+    let conn = open()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT name, avatar, status, status_description, bio FROM presets ORDER BY name",
+    )?;
+    let preset_iter = stmt.query_map([], preset_from_row)?;
+
+    let mut presets = Vec::new();
+    for preset in preset_iter {
+        presets.push(preset?);
+    }
+
+    Ok(presets)
+}
+
+/// Returns whether there was a preset to delete.
+pub fn delete_preset(name: &str) -> Result<bool, rusqlite::Error> {
+    create_preset_db()?;
+    let conn = open()?;
+
+    let result = conn.execute("DELETE FROM presets WHERE name = ?1", [name])?;
+
+    Ok(result > 0)
+}
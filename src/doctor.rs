@@ -0,0 +1,192 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::{debug, warn};
+use vrchatapi::apis::{self, configuration::Configuration};
+use vrchatapi::models::Avatar;
+
+use crate::db;
+use crate::log::{trace_request, trace_response};
+use crate::output::Record;
+use crate::ratelimit;
+use crate::switch::resolve_selector;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AliasHealth {
+    Ok,
+    /// Another alias points at the same avatar.
+    Duplicate,
+    /// The avatar is not in the local database.
+    Missing,
+    /// The API says the avatar no longer exists.
+    Deleted,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AliasCheck {
+    pub alias: String,
+    pub avatar_id: String,
+    pub avatar_name: Option<String>,
+    pub status: AliasHealth,
+    /// An avatar with the recorded name that a broken alias can be re-pointed at.
+    pub suggestion: Option<String>,
+    pub note: Option<String>,
+}
+
+impl Record for AliasCheck {
+    const COLUMNS: &'static [&'static str] = &[
+        "alias",
+        "avatar_id",
+        "avatar_name",
+        "status",
+        "suggestion",
+        "note",
+    ];
+}
+
+/// Cross-checks every alias against the local database, and against the API when a
+/// configuration is given. With `fix`, also records the current avatar name of healthy
+/// aliases and the avatar selector aliases now select; otherwise nothing is written.
+pub async fn check_aliases(
+    config: Option<&Configuration>,
+    fix: bool,
+) -> Result<Vec<AliasCheck>, rusqlite::Error> {
+    db::create_avatar_db()?;
+    db::create_alias_db()?;
+
+    let aliases = db::get_all_aliases()?;
+    let avatars: HashMap<String, Avatar> = db::get_all_avatars()?
+        .into_iter()
+        .map(|avatar| (avatar.id.clone(), avatar))
+        .collect();
+
+    let mut aliases_by_avatar: HashMap<&str, Vec<&str>> = HashMap::new();
+    for alias in &aliases {
+        aliases_by_avatar
+            .entry(&alias.avatar_id)
+            .or_default()
+            .push(&alias.name);
+    }
+
+    let mut checks = Vec::new();
+    for alias in &aliases {
+        // Selector aliases are healthy as long as the selector matches exactly one avatar.
+        if let Some(selector) = &alias.selector {
+            let check = match resolve_selector(selector) {
+                Ok(avatar) => {
+                    if fix && avatar.id != alias.avatar_id {
+                        db::set_alias_match(&alias.name, &avatar.id, &avatar.name)?;
+                    }
+                    AliasCheck {
+                        alias: alias.name.clone(),
+                        avatar_id: avatar.id,
+                        avatar_name: Some(avatar.name),
+                        status: AliasHealth::Ok,
+                        suggestion: None,
+                        note: Some(format!("Selects {}", selector)),
+                    }
+                }
+                Err(e) => AliasCheck {
+                    alias: alias.name.clone(),
+                    avatar_id: alias.avatar_id.clone(),
+                    avatar_name: alias.avatar_name.clone(),
+                    status: AliasHealth::Missing,
+                    suggestion: None,
+                    note: Some(e),
+                },
+            };
+            checks.push(check);
+            continue;
+        }
+
+        let local = avatars.get(&alias.avatar_id);
+        if fix
+            && let Some(avatar) = local
+            && alias.avatar_name.as_deref() != Some(avatar.name.as_str())
+        {
+            debug!(
+                "Recording avatar name '{}' for alias '{}'",
+                avatar.name, alias.name
+            );
+            db::set_alias_avatar_name(&alias.name, &avatar.name)?;
+        }
+
+        let remote = match config {
+            Some(config) => exists_remotely(config, &alias.avatar_id).await,
+            None => None,
+        };
+
+        let (status, mut note) = match (local, remote) {
+            (_, Some(false)) => (AliasHealth::Deleted, None),
+            (None, Some(true)) => (
+                AliasHealth::Ok,
+                Some(String::from("Not in the local database, but exists")),
+            ),
+            (None, None) => (AliasHealth::Missing, None),
+            (Some(_), _) => {
+                let others: Vec<&str> = aliases_by_avatar[alias.avatar_id.as_str()]
+                    .iter()
+                    .copied()
+                    .filter(|name| *name != alias.name)
+                    .collect();
+                if others.is_empty() {
+                    (AliasHealth::Ok, None)
+                } else {
+                    (
+                        AliasHealth::Duplicate,
+                        Some(format!("Also aliased as {}", others.join(", "))),
+                    )
+                }
+            }
+        };
+
+        let avatar_name = local
+            .map(|avatar| avatar.name.clone())
+            .or(alias.avatar_name.clone());
+        let mut suggestion = None;
+        if matches!(status, AliasHealth::Missing | AliasHealth::Deleted) {
+            match &alias.avatar_name {
+                Some(name) => {
+                    let candidates: Vec<&Avatar> = avatars
+                        .values()
+                        .filter(|avatar| avatar.name == *name && avatar.id != alias.avatar_id)
+                        .collect();
+                    match candidates.as_slice() {
+                        [avatar] => suggestion = Some(avatar.id.clone()),
+                        [] => note = Some(format!("No other avatar named '{}'", name)),
+                        many => note = Some(format!("{} avatars named '{}'", many.len(), name)),
+                    }
+                }
+                None => note = Some(String::from("No avatar name recorded to match by")),
+            }
+        }
+
+        checks.push(AliasCheck {
+            alias: alias.name.clone(),
+            avatar_id: alias.avatar_id.clone(),
+            avatar_name,
+            status,
+            suggestion,
+            note,
+        });
+    }
+
+    Ok(checks)
+}
+
+/// `Some(false)` if the API says the avatar is gone, `None` if it could not tell.
+async fn exists_remotely(config: &Configuration, avatar_id: &str) -> Option<bool> {
+    ratelimit::api().wait().await;
+    trace_request("get_avatar", avatar_id);
+    let result = apis::avatars_api::get_avatar(config, avatar_id).await;
+    trace_response("get_avatar", &result);
+
+    match result {
+        Ok(_) => Some(true),
+        Err(apis::Error::ResponseError(response)) if response.status.as_u16() == 404 => Some(false),
+        Err(e) => {
+            warn!("Could not check {} with the API: {}", avatar_id, e);
+            None
+        }
+    }
+}
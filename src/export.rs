@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+use vrchatapi::apis::configuration::Configuration;
+use vrchatapi::models::Avatar;
+
+use crate::db::{self, avatar_platforms};
+
+const THUMBNAIL_DIR: &str = "thumbnails";
+
+pub async fn export_html(
+    config: Option<Configuration>,
+    dir: &Path,
+    avatars: Vec<Avatar>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    db::create_alias_db()?;
+
+    let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+    for alias in db::get_all_aliases()? {
+        aliases.entry(alias.avatar_id).or_default().push(alias.name);
+    }
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for (avatar_id, tag) in db::get_all_tags()? {
+        tags.entry(avatar_id).or_default().push(tag);
+    }
+
+    std::fs::create_dir_all(dir.join(THUMBNAIL_DIR))?;
+
+    let mut cards = String::new();
+    for avatar in &avatars {
+        let thumbnail = match &config {
+            Some(config) => cache_thumbnail(config, dir, avatar).await,
+            None => None,
+        };
+        let avatar_aliases = aliases.get(&avatar.id).cloned().unwrap_or_default();
+        let avatar_tags = tags.get(&avatar.id).cloned().unwrap_or_default();
+        cards.push_str(&render_card(
+            avatar,
+            &avatar_aliases,
+            &avatar_tags,
+            thumbnail.as_deref(),
+        ));
+    }
+
+    std::fs::write(
+        dir.join("index.html"),
+        PAGE_TEMPLATE
+            .replace("{{count}}", &avatars.len().to_string())
+            .replace("{{cards}}", &cards),
+    )?;
+
+    info!(
+        "Exported {} avatars to {}",
+        avatars.len(),
+        dir.join("index.html").display()
+    );
+    Ok(())
+}
+
+/// Downloads a thumbnail unless this version of it is already in `dir`.
+/// Returns the path relative to the gallery page.
+async fn cache_thumbnail(config: &Configuration, dir: &Path, avatar: &Avatar) -> Option<String> {
+    if avatar.thumbnail_image_url.is_empty() {
+        return None;
+    }
+
+    let relative = format!("{}/{}-{}.png", THUMBNAIL_DIR, avatar.id, avatar.version);
+    let path = dir.join(&relative);
+    if path.exists() {
+        return Some(relative);
+    }
+
+    debug!("Downloading thumbnail for {} ({})", avatar.name, avatar.id);
+    let mut request = config.client.get(&avatar.thumbnail_image_url);
+    if let Some(user_agent) = &config.user_agent {
+        request = request.header(reqwest::header::USER_AGENT, user_agent);
+    }
+
+    let result = match request.send().await.and_then(|r| r.error_for_status()) {
+        Ok(response) => match response.bytes().await {
+            Ok(bytes) => match std::fs::write(&path, &bytes) {
+                Ok(_) => Some(relative),
+                Err(e) => {
+                    warn!("Failed to save thumbnail for {}: {}", avatar.id, e);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("Failed to download thumbnail for {}: {}", avatar.id, e);
+                None
+            }
+        },
+        Err(e) => {
+            warn!("Failed to download thumbnail for {}: {}", avatar.id, e);
+            None
+        }
+    };
+
+    sleep(Duration::from_secs(1)).await; // To avoid rate limiting
+    result
+}
+
+fn render_card(
+    avatar: &Avatar,
+    aliases: &[String],
+    tags: &[String],
+    thumbnail: Option<&str>,
+) -> String {
+    let image = match thumbnail {
+        Some(src) => format!(
+            r#"<img src="{}" alt="{}" loading="lazy">"#,
+            escape_html(src),
+            escape_html(&avatar.name)
+        ),
+        None => r#"<div class="noimage">no image</div>"#.to_string(),
+    };
+
+    let badges: String = avatar_platforms(avatar)
+        .iter()
+        .map(|platform| {
+            format!(
+                r#"<span class="badge {}">{}</span>"#,
+                escape_html(platform),
+                platform_label(platform)
+            )
+        })
+        .collect();
+
+    let alias_list: String = aliases
+        .iter()
+        .map(|alias| format!(r#"<span class="alias">{}</span>"#, escape_html(alias)))
+        .collect();
+
+    let tag_list: String = tags
+        .iter()
+        .map(|tag| format!(r#"<span class="tag">#{}</span>"#, escape_html(tag)))
+        .collect();
+
+    let command = format!("vavc switch -i {}", avatar.id);
+    let search_text = format!(
+        "{} {} {} {}",
+        avatar.name,
+        avatar.description,
+        aliases.join(" "),
+        tags.iter()
+            .map(|tag| format!("#{}", tag))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+
+    format!(
+        r#"<div class="card" data-search="{search}">
+  {image}
+  <div class="body">
+    <h2>{name}</h2>
+    <div class="badges">{badges}</div>
+    <p class="description">{description}</p>
+    <div class="aliases">{aliases}{tags}</div>
+    <div class="command"><code>{command}</code><button data-command="{command}">Copy</button></div>
+  </div>
+</div>
+"#,
+        search = escape_html(&search_text.to_lowercase()),
+        image = image,
+        name = escape_html(&avatar.name),
+        badges = badges,
+        description = escape_html(&avatar.description),
+        aliases = alias_list,
+        tags = tag_list,
+        command = escape_html(&command),
+    )
+}
+
+fn platform_label(platform: &str) -> &str {
+    match platform {
+        "standalonewindows" => "PC",
+        "android" => "Quest",
+        "ios" => "iOS",
+        _ => "Other",
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+const PAGE_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="ja">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>vavc avatars</title>
+<style>
+body { font-family: sans-serif; margin: 0; background: #1e1e24; color: #eee; }
+header { position: sticky; top: 0; padding: 12px 16px; background: #2a2a33; display: flex; gap: 12px; align-items: center; }
+header input { flex: 1; padding: 8px; font-size: 16px; border-radius: 4px; border: none; }
+main { display: grid; grid-template-columns: repeat(auto-fill, minmax(240px, 1fr)); gap: 16px; padding: 16px; }
+.card { background: #2a2a33; border-radius: 8px; overflow: hidden; }
+.card img, .card .noimage { width: 100%; aspect-ratio: 4 / 3; object-fit: cover; display: block; background: #111; }
+.card .noimage { display: flex; align-items: center; justify-content: center; color: #777; }
+.body { padding: 8px 12px 12px; }
+h2 { font-size: 16px; margin: 0 0 6px; word-break: break-all; }
+.description { font-size: 13px; color: #bbb; white-space: pre-wrap; }
+.badge, .alias, .tag { display: inline-block; font-size: 11px; padding: 2px 6px; border-radius: 3px; margin: 0 4px 4px 0; }
+.badge { background: #555; }
+.badge.standalonewindows { background: #2f6fb0; }
+.badge.android { background: #2e8b57; }
+.badge.ios { background: #8a5cb8; }
+.alias { background: #6b4a1f; }
+.tag { background: #3d5a3d; }
+.command { display: flex; gap: 6px; align-items: center; margin-top: 8px; }
+.command code { flex: 1; font-size: 11px; overflow: hidden; text-overflow: ellipsis; white-space: nowrap; }
+.hidden { display: none; }
+</style>
+</head>
+<body>
+<header>
+  <input id="search" type="search" placeholder="Search name, description, alias or #tag">
+  <span id="count">{{count}}</span>
+</header>
+<main>
+{{cards}}</main>
+<script>
+const cards = Array.from(document.querySelectorAll(".card"));
+const count = document.getElementById("count");
+document.getElementById("search").addEventListener("input", (e) => {
+  const words = e.target.value.toLowerCase().split(/\s+/).filter((w) => w);
+  let shown = 0;
+  for (const card of cards) {
+    const hit = words.every((w) => card.dataset.search.includes(w));
+    card.classList.toggle("hidden", !hit);
+    if (hit) shown++;
+  }
+  count.textContent = shown;
+});
+document.addEventListener("click", (e) => {
+  const command = e.target.dataset && e.target.dataset.command;
+  if (!command) return;
+  navigator.clipboard.writeText(command).then(() => {
+    e.target.textContent = "Copied";
+    setTimeout(() => (e.target.textContent = "Copy"), 1000);
+  });
+});
+</script>
+</body>
+</html>
+"#;
@@ -0,0 +1,161 @@
+use tracing::{info, warn};
+use vrchatapi::apis::{self, configuration::Configuration};
+use vrchatapi::models::{AddFavoriteRequest, FavoriteType, UpdateFavoriteGroupRequest};
+
+use crate::log::{trace_request, trace_response};
+use crate::switch::get_current_user;
+use crate::{db, ratelimit};
+
+/// Looks a favourite group up by name or display name in the local copy.
+pub fn find_group(group: &str) -> Result<String, String> {
+    match db::find_favorite_group(group) {
+        Ok(Some(name)) => Ok(name),
+        Ok(None) => Err(format!(
+            "No favourite group named '{}'; run `vavc fetch --favorites` to refresh them",
+            group
+        )),
+        Err(e) => Err(format!("Error retrieving favourite groups: {}", e)),
+    }
+}
+
+fn find_favorite_id(avatar_id: &str) -> Result<String, String> {
+    match db::get_favorite_id(avatar_id) {
+        Ok(Some(favorite_id)) => Ok(favorite_id),
+        Ok(None) => Err(format!(
+            "{} is not in any favourite group; run `vavc fetch --favorites` if it should be",
+            avatar_id
+        )),
+        Err(e) => Err(format!("Error retrieving favourites: {}", e)),
+    }
+}
+
+pub async fn add_favorite(
+    config: &Configuration,
+    avatar_id: &str,
+    group: &str,
+) -> Result<(), String> {
+    let group = find_group(group)?;
+    let request = AddFavoriteRequest {
+        r#type: FavoriteType::Avatar,
+        favorite_id: avatar_id.to_string(),
+        tags: vec![group.clone()],
+    };
+
+    ratelimit::api().wait().await;
+    trace_request("add_favorite", &format!("{} tags={}", avatar_id, group));
+    let result = apis::favorites_api::add_favorite(config, Some(request)).await;
+    trace_response("add_favorite", &result);
+    let favorite = result.map_err(|e| format!("Failed to add favourite: {}", e))?;
+
+    db::add_favorite(&favorite).map_err(|e| format!("Error storing favourite: {}", e))?;
+    info!("Added {} to favourite group '{}'", avatar_id, group);
+    Ok(())
+}
+
+/// Unfavourites an avatar. VRChat removes it from every group it is in.
+pub async fn remove_favorite(config: &Configuration, avatar_id: &str) -> Result<(), String> {
+    let favorite_id = find_favorite_id(avatar_id)?;
+
+    ratelimit::api().wait().await;
+    trace_request("remove_favorite", &favorite_id);
+    let result = apis::favorites_api::remove_favorite(config, &favorite_id).await;
+    trace_response("remove_favorite", &result);
+    result.map_err(|e| format!("Failed to remove favourite: {}", e))?;
+
+    db::remove_favorite(&favorite_id).map_err(|e| format!("Error removing favourite: {}", e))?;
+    info!("Removed {} from favourites", avatar_id);
+    Ok(())
+}
+
+/// Moves an avatar to another group. The API has no move, so this is a remove and an add;
+/// if the add fails, the avatar is put back in the groups it was in.
+pub async fn move_favorite(
+    config: &Configuration,
+    avatar_id: &str,
+    group: &str,
+) -> Result<(), String> {
+    // Check the target group before the avatar loses its current one.
+    let group = find_group(group)?;
+    let original: Vec<String> = db::get_favorites(None)
+        .map_err(|e| format!("Error retrieving favourites: {}", e))?
+        .into_iter()
+        .filter(|(_, id, _)| id == avatar_id)
+        .map(|(group, _, _)| group)
+        .collect();
+
+    remove_favorite(config, avatar_id).await?;
+    let Err(error) = add_favorite(config, avatar_id, &group).await else {
+        return Ok(());
+    };
+
+    warn!("{}; putting {} back", error, avatar_id);
+    let mut restored = Vec::new();
+    let mut lost = Vec::new();
+    for original in original {
+        match add_favorite(config, avatar_id, &original).await {
+            Ok(()) => restored.push(original),
+            Err(e) => {
+                warn!("{}", e);
+                lost.push(original);
+            }
+        }
+    }
+
+    Err(match (restored.is_empty(), lost.is_empty()) {
+        (true, true) => format!("{}; {} is no longer favourited", error, avatar_id),
+        (false, true) => format!(
+            "{}; {} was put back in {}",
+            error,
+            avatar_id,
+            restored.join(", ")
+        ),
+        (true, false) => format!(
+            "{}; putting {} back in {} also failed, so it is no longer favourited",
+            error,
+            avatar_id,
+            lost.join(", ")
+        ),
+        (false, false) => format!(
+            "{}; {} was put back in {} but not in {}",
+            error,
+            avatar_id,
+            restored.join(", "),
+            lost.join(", ")
+        ),
+    })
+}
+
+pub async fn rename_group(
+    config: &Configuration,
+    group: &str,
+    display_name: &str,
+) -> Result<(), String> {
+    let group = find_group(group)?;
+    let user = get_current_user(config).await?;
+    let request = UpdateFavoriteGroupRequest {
+        display_name: Some(display_name.to_string()),
+        visibility: None,
+        tags: None,
+    };
+
+    ratelimit::api().wait().await;
+    trace_request(
+        "update_favorite_group",
+        &format!("avatar/{} displayName={}", group, display_name),
+    );
+    let result = apis::favorites_api::update_favorite_group(
+        config,
+        "avatar",
+        &group,
+        &user.id,
+        Some(request),
+    )
+    .await;
+    trace_response("update_favorite_group", &result);
+    result.map_err(|e| format!("Failed to rename favourite group: {}", e))?;
+
+    db::rename_favorite_group(&group, display_name)
+        .map_err(|e| format!("Error storing favourite group: {}", e))?;
+    info!("Renamed favourite group '{}' to '{}'", group, display_name);
+    Ok(())
+}
@@ -1,23 +1,33 @@
 use std::time::Duration;
 use tokio::time::sleep;
+use tracing::{debug, info};
 use vrchatapi::apis;
 use vrchatapi::apis::configuration::Configuration;
-use vrchatapi::models::Avatar;
+use vrchatapi::models::{Avatar, Favorite, FavoriteGroup, FavoriteType};
 
-pub async fn fetch_avatars(config: Configuration) -> Vec<Avatar> {
-    println!("Fetching avatars...");
+use crate::log::{trace_request, trace_response};
+use crate::{config, ratelimit};
+
+/// All of our own avatars. Fails as a whole, so that a partial list never replaces
+/// what is stored.
+pub async fn fetch_avatars(config: Configuration) -> Result<Vec<Avatar>, String> {
+    info!("Fetching avatars...");
 
     let mut out = Vec::<Avatar>::new();
     let mut avatar_count: usize = 0;
 
     loop {
+        trace_request(
+            "search_avatars",
+            &format!("user=me offset={}", avatar_count),
+        );
         let avatars = apis::avatars_api::search_avatars(
             &config,
             Some(false),
             Some(vrchatapi::models::SortOption::Name),
             Some("me"),
             None,
-            Some(60),
+            Some(config::current().fetch_page_size),
             None,
             Some(avatar_count.try_into().expect("Negative avatar count wtf")),
             None,
@@ -28,38 +38,139 @@ pub async fn fetch_avatars(config: Configuration) -> Vec<Avatar> {
             None,
         )
         .await;
+        trace_response("search_avatars", &avatars);
 
-        if let Ok(avatars) = avatars {
-            let got = avatars.len();
-            if got == 0 {
-                break;
-            }
-
-            println!(
-                "Fetched {} avatars, total so far: {}",
-                got,
-                avatar_count + got
-            );
-
-            avatar_count += got;
-            avatars.iter().for_each(|avatar| {
-                println!("{}: {}", avatar.name, avatar.id);
-                out.push(avatar.clone());
-            });
-
-            println!("Sleep for 5 seconds to avoid rate limiting...");
-            sleep(Duration::from_secs(5)).await; // To avoid rate limiting
-            continue;
-        } else {
-            eprintln!("Failed to fetch avatars: {}", avatars.err().unwrap());
+        let avatars = avatars.map_err(|e| format!("Failed to fetch avatars: {}", e))?;
+        let got = avatars.len();
+        if got == 0 {
             break;
         }
+
+        info!(
+            "Fetched {} avatars, total so far: {}",
+            got,
+            avatar_count + got
+        );
+
+        avatar_count += got;
+        out.extend(avatars);
+
+        let delay = config::current().fetch_delay_secs;
+        debug!("Sleep for {} seconds to avoid rate limiting...", delay);
+        sleep(Duration::from_secs(delay)).await; // To avoid rate limiting
     }
 
-    println!(
+    info!(
         "Finished fetching avatars. Total avatars fetched: {}",
         avatar_count
     );
 
-    out
+    Ok(out)
+}
+
+/// Our avatar favourite groups, the favourites in them and the favourited avatars.
+/// Like `fetch_avatars` this fails as a whole.
+pub async fn fetch_favorites(
+    config: &Configuration,
+) -> Result<(Vec<FavoriteGroup>, Vec<Favorite>, Vec<Avatar>), String> {
+    info!("Fetching favourites...");
+    let page_size = config::current().fetch_page_size;
+    let delay = Duration::from_secs(config::current().fetch_delay_secs);
+
+    let mut groups = Vec::<FavoriteGroup>::new();
+    let mut offset: usize = 0;
+    loop {
+        ratelimit::api().wait().await;
+        trace_request("get_favorite_groups", &format!("offset={}", offset));
+        let page = apis::favorites_api::get_favorite_groups(
+            config,
+            Some(page_size),
+            Some(offset.try_into().expect("Too many favourite groups")),
+            None,
+            None,
+        )
+        .await;
+        trace_response("get_favorite_groups", &page);
+
+        let page = page.map_err(|e| format!("Failed to fetch favourite groups: {}", e))?;
+        if page.is_empty() {
+            break;
+        }
+        offset += page.len();
+        groups.extend(
+            page.into_iter()
+                .filter(|group| group.r#type == FavoriteType::Avatar),
+        );
+    }
+    debug!("Fetched {} avatar favourite groups", groups.len());
+
+    let mut favorites = Vec::<Favorite>::new();
+    loop {
+        sleep(delay).await; // To avoid rate limiting
+        ratelimit::api().wait().await;
+        trace_request(
+            "get_favorites",
+            &format!("type=avatar offset={}", favorites.len()),
+        );
+        let page = apis::favorites_api::get_favorites(
+            config,
+            Some(page_size),
+            Some(favorites.len().try_into().expect("Too many favourites")),
+            Some("avatar"),
+            None,
+        )
+        .await;
+        trace_response("get_favorites", &page);
+
+        let page = page.map_err(|e| format!("Failed to fetch favourites: {}", e))?;
+        if page.is_empty() {
+            break;
+        }
+        favorites.extend(page);
+        debug!("Fetched {} favourites so far", favorites.len());
+    }
+
+    let mut avatars = Vec::<Avatar>::new();
+    loop {
+        sleep(delay).await; // To avoid rate limiting
+        ratelimit::api().wait().await;
+        trace_request(
+            "get_favorited_avatars",
+            &format!("offset={}", avatars.len()),
+        );
+        let page = apis::avatars_api::get_favorited_avatars(
+            config,
+            None,
+            None,
+            Some(page_size),
+            None,
+            Some(avatars.len().try_into().expect("Too many favourites")),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+        trace_response("get_favorited_avatars", &page);
+
+        let page = page.map_err(|e| format!("Failed to fetch favourited avatars: {}", e))?;
+        if page.is_empty() {
+            break;
+        }
+        avatars.extend(page);
+        info!("Fetched {} favourited avatars so far", avatars.len());
+    }
+
+    info!(
+        "Finished fetching favourites: {} groups, {} favourites, {} avatars",
+        groups.len(),
+        favorites.len(),
+        avatars.len()
+    );
+
+    Ok((groups, favorites, avatars))
 }
@@ -0,0 +1,35 @@
+use vrchatapi::models::Avatar;
+
+use crate::config;
+use crate::db::{self, avatar_platforms};
+use crate::switch::resolve_group;
+
+/// Applies `--platform`, falling back to `default_platform` from the config.
+pub fn filter_by_platform(avatars: Vec<Avatar>, platform: Option<String>) -> Vec<Avatar> {
+    let platform = platform.or(config::current().default_platform.clone());
+    match platform.as_deref() {
+        None | Some("all") => avatars,
+        Some(platform) => avatars
+            .into_iter()
+            .filter(|avatar| avatar_platforms(avatar).iter().any(|p| p == platform))
+            .collect(),
+    }
+}
+
+/// Keeps avatars carrying `tag` and aliased in `group`; either filter may be left out.
+pub fn filter_by_tag_and_group(
+    avatars: Vec<Avatar>,
+    tag: Option<String>,
+    group: Option<String>,
+) -> Result<Vec<Avatar>, rusqlite::Error> {
+    let tagged = tag
+        .map(|tag| db::get_avatar_ids_with_tag(&tag))
+        .transpose()?;
+    let grouped = group.map(|group| resolve_group(&group)).transpose()?;
+
+    Ok(avatars
+        .into_iter()
+        .filter(|avatar| tagged.as_ref().is_none_or(|ids| ids.contains(&avatar.id)))
+        .filter(|avatar| grouped.as_ref().is_none_or(|ids| ids.contains(&avatar.id)))
+        .collect())
+}
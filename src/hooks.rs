@@ -0,0 +1,272 @@
+use serde::Serialize;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+
+use crate::config::{self, Hook, HookWhen};
+use crate::db;
+
+/// What a hook is told about the switch, as JSON on stdin and as `VAVC_*` variables.
+#[derive(Debug, Serialize)]
+struct HookEvent<'a> {
+    event: &'static str,
+    avatar_id: &'a str,
+    avatar_name: Option<String>,
+    alias: Option<String>,
+    resolved_by: &'a str,
+    input: &'a str,
+}
+
+impl<'a> HookEvent<'a> {
+    fn new(event: &'static str, avatar_id: &'a str, resolved_by: &'a str, input: &'a str) -> Self {
+        let avatar_name = db::get_avatar_by_id(avatar_id)
+            .ok()
+            .flatten()
+            .map(|avatar| avatar.name);
+        // The alias that was asked for, or else any alias of the avatar.
+        let alias = if resolved_by == "alias" {
+            Some(input.to_string())
+        } else {
+            db::get_all_aliases().ok().and_then(|aliases| {
+                aliases
+                    .into_iter()
+                    .find(|alias| alias.avatar_id == avatar_id)
+                    .map(|alias| alias.name)
+            })
+        };
+
+        HookEvent {
+            event,
+            avatar_id,
+            avatar_name,
+            alias,
+            resolved_by,
+            input,
+        }
+    }
+}
+
+fn hooks(when: HookWhen) -> Vec<&'static Hook> {
+    config::current()
+        .hooks
+        .iter()
+        .filter(|hook| hook.when == when)
+        .collect()
+}
+
+/// Runs the pre-switch hooks in order. The first one to exit non-zero vetoes the switch
+/// and its output becomes the reason. A hook that cannot run or times out does not.
+pub async fn pre_switch(avatar_id: &str, resolved_by: &str, input: &str) -> Result<(), String> {
+    let hooks = hooks(HookWhen::Pre);
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let event = HookEvent::new("pre_switch", avatar_id, resolved_by, input);
+    for hook in hooks {
+        if let Some(reason) = run_hook(hook, &event).await {
+            return Err(reason);
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the post-switch hooks in order. Their failures are only logged.
+pub async fn post_switch(avatar_id: &str, resolved_by: &str, input: &str) {
+    let hooks = hooks(HookWhen::Post);
+    if hooks.is_empty() {
+        return;
+    }
+
+    let event = HookEvent::new("post_switch", avatar_id, resolved_by, input);
+    for hook in hooks {
+        if let Some(reason) = run_hook(hook, &event).await {
+            warn!("Post-switch hook '{}' failed: {}", hook.command, reason);
+        }
+    }
+}
+
+/// Returns why the hook failed, if it exited non-zero.
+async fn run_hook(hook: &Hook, event: &HookEvent<'_>) -> Option<String> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let body = serde_json::to_string(event).expect("Hook event is not serializable");
+
+    debug!("Running hook: {}", hook.command);
+    let mut command = Command::new(shell);
+    // Its own process group, so a timeout can kill whatever the shell started too.
+    #[cfg(unix)]
+    command.process_group(0);
+    let mut child = match command
+        .arg(flag)
+        .arg(&hook.command)
+        .env("VAVC_EVENT", event.event)
+        .env("VAVC_AVATAR_ID", event.avatar_id)
+        .env(
+            "VAVC_AVATAR_NAME",
+            event.avatar_name.as_deref().unwrap_or_default(),
+        )
+        .env("VAVC_ALIAS", event.alias.as_deref().unwrap_or_default())
+        .env("VAVC_RESOLVED_BY", event.resolved_by)
+        .env("VAVC_INPUT", event.input)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Could not run hook '{}': {}", hook.command, e);
+            return None;
+        }
+    };
+
+    let pid = child.id();
+    if let Some(mut stdin) = child.stdin.take() {
+        // Hooks that do not read stdin may already be gone.
+        let _ = stdin.write_all(body.as_bytes()).await;
+    }
+
+    let output = match timeout(
+        Duration::from_secs(hook.timeout_secs),
+        child.wait_with_output(),
+    )
+    .await
+    {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            warn!("Hook '{}' failed to run: {}", hook.command, e);
+            return None;
+        }
+        Err(_) => {
+            kill_group(pid);
+            warn!(
+                "Hook '{}' timed out after {}s and was killed",
+                hook.command, hook.timeout_secs
+            );
+            return None;
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stdout.trim().is_empty() {
+        debug!("Hook '{}' said: {}", hook.command, stdout.trim());
+    }
+
+    if output.status.success() {
+        return None;
+    }
+    let reason = [stderr.trim(), stdout.trim()]
+        .into_iter()
+        .find(|text| !text.is_empty())
+        .map(|text| text.to_string())
+        .unwrap_or_else(|| format!("exited with {}", output.status));
+    info!("Hook '{}' exited non-zero: {}", hook.command, reason);
+    Some(reason)
+}
+
+/// Kills the hook's process group. Elsewhere only the shell itself is killed, on drop.
+#[cfg(unix)]
+fn kill_group(pid: Option<u32>) {
+    if let Some(pid) = pid.and_then(|pid| libc::pid_t::try_from(pid).ok()) {
+        // SAFETY: killpg only sends a signal; the group was created for this hook.
+        unsafe {
+            libc::killpg(pid, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(_pid: Option<u32>) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use crate::test_support;
+
+    fn hook(when: HookWhen, command: &str, timeout_secs: u64) -> Hook {
+        Hook {
+            when,
+            command: command.to_string(),
+            timeout_secs,
+        }
+    }
+
+    #[tokio::test]
+    async fn non_zero_exit_vetoes_with_its_output() {
+        test_support::setup();
+        let event = HookEvent::new("pre_switch", "avtr_hook", "id", "avtr_hook");
+
+        let veto = hook(HookWhen::Pre, "echo ignored; echo nope >&2; exit 3", 5);
+        assert_eq!(run_hook(&veto, &event).await.as_deref(), Some("nope"));
+
+        let silent = hook(HookWhen::Pre, "exit 3", 5);
+        assert_eq!(
+            run_hook(&silent, &event).await.as_deref(),
+            Some("exited with exit status: 3")
+        );
+
+        let allow = hook(HookWhen::Pre, "true", 5);
+        assert_eq!(run_hook(&allow, &event).await, None);
+    }
+
+    #[tokio::test]
+    async fn timeout_kills_the_whole_hook() {
+        test_support::setup();
+        let marker = std::env::current_dir().unwrap().join("hook-timeout-marker");
+        let event = HookEvent::new("pre_switch", "avtr_hook", "id", "avtr_hook");
+
+        // The background child would outlive the shell if only the shell were killed.
+        let command = format!("(sleep 2; touch '{}') & wait; exit 1", marker.display());
+        let started = std::time::Instant::now();
+        assert_eq!(
+            run_hook(&hook(HookWhen::Pre, &command, 1), &event).await,
+            None
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert!(!marker.exists(), "the hook's child survived the timeout");
+    }
+
+    #[tokio::test]
+    async fn post_hook_gets_the_event_in_env_and_stdin() {
+        test_support::setup();
+        let dir = std::env::current_dir().unwrap();
+        let env_file = dir.join("hook-env");
+        let stdin_file = dir.join("hook-stdin");
+        let event = HookEvent::new("post_switch", "avtr_hook", "alias", "myalias");
+
+        let command = format!(
+            "printf '%s\\n' \"$VAVC_EVENT\" \"$VAVC_AVATAR_ID\" \"$VAVC_ALIAS\" \
+             \"$VAVC_RESOLVED_BY\" \"$VAVC_INPUT\" > '{}'; cat > '{}'",
+            env_file.display(),
+            stdin_file.display()
+        );
+        assert_eq!(
+            run_hook(&hook(HookWhen::Post, &command, 5), &event).await,
+            None
+        );
+
+        let env = std::fs::read_to_string(&env_file).unwrap();
+        assert_eq!(env, "post_switch\navtr_hook\nmyalias\nalias\nmyalias\n");
+
+        let body: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&stdin_file).unwrap()).unwrap();
+        assert_eq!(body["event"], "post_switch");
+        assert_eq!(body["avatar_id"], "avtr_hook");
+        assert_eq!(body["alias"], "myalias");
+        assert_eq!(body["resolved_by"], "alias");
+        assert_eq!(body["input"], "myalias");
+    }
+}
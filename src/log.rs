@@ -0,0 +1,81 @@
+use serde::Serialize;
+use tracing::trace;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{EnvFilter, Layer, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+use vrchatapi::apis;
+
+const LOG_DIR: &str = "./logs";
+
+/// Sets up stderr logging (and the rotating log file if asked for).
+/// `VAVC_LOG` takes precedence over `-v`/`-q` and accepts `EnvFilter` directives.
+/// The returned guard must be kept alive so buffered file logs get flushed.
+pub fn init(verbose: u8, quiet: u8, log_file: bool) -> Option<WorkerGuard> {
+    let level = match verbose as i16 - quiet as i16 {
+        i16::MIN..=-3 => "off",
+        -2 => "error",
+        -1 => "warn",
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    };
+    let filter = || {
+        EnvFilter::try_from_env("VAVC_LOG")
+            .unwrap_or_else(|_| EnvFilter::new(format!("warn,vavc={}", level)))
+    };
+
+    let stderr_layer = fmt::layer()
+        .with_writer(std::io::stderr)
+        .without_time()
+        .with_target(false)
+        .with_filter(filter());
+
+    let (file_layer, guard) = if log_file {
+        let appender = tracing_appender::rolling::daily(LOG_DIR, "vavc.log");
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+        let layer = fmt::layer()
+            .with_writer(writer)
+            .with_ansi(false)
+            .with_filter(filter());
+        (Some(layer), Some(guard))
+    } else {
+        (None, None)
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr_layer)
+        .with(file_layer)
+        .init();
+
+    guard
+}
+
+/// Replaces cookie values so that `auth=authcookie_xxx` becomes `auth=<redacted>`.
+pub fn redact_cookies(cookies: &str) -> String {
+    cookies
+        .split(';')
+        .map(|cookie| match cookie.split_once('=') {
+            Some((name, _)) => format!("{}=<redacted>", name.trim()),
+            None => cookie.trim().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+pub fn trace_request(operation: &str, details: &str) {
+    trace!("--> {} {}", operation, details);
+}
+
+pub fn trace_response<T: Serialize, E>(operation: &str, result: &Result<T, apis::Error<E>>) {
+    match result {
+        Ok(body) => trace!(
+            "<-- {} ok: {}",
+            operation,
+            serde_json::to_string(body).unwrap_or_default()
+        ),
+        Err(apis::Error::ResponseError(response)) => trace!(
+            "<-- {} {}: {}",
+            operation, response.status, response.content
+        ),
+        Err(e) => trace!("<-- {} failed: {}", operation, e),
+    }
+}
@@ -1,29 +1,101 @@
+#[cfg(unix)]
+mod agent;
+mod aliasfile;
 mod auth;
+mod avatar;
+mod config;
+mod current;
+mod daemon;
 mod db;
+mod doctor;
+mod export;
+mod favorites;
 mod fetch;
+mod filter;
+mod hooks;
+mod log;
+mod output;
+mod pipeline;
+mod preset;
+mod random;
+mod ratelimit;
 mod secret;
+mod serve;
+mod stats;
 mod switch;
+#[cfg(test)]
+mod test_support;
+mod user;
 
-use clap::ArgGroup;
-use clap::{Parser, Subcommand, command};
+use clap::{ArgAction, ArgGroup, Args, ValueEnum};
+use clap::{Parser, Subcommand};
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+use vrchatapi::models::Avatar;
 
+use crate::aliasfile::AliasFileFormat;
 use crate::auth::check_auth_cookie;
 use crate::auth::get_new_auth_cookie;
 use crate::auth::make_configuration_with_cookies;
-use crate::db::{create_alias_db, get_all_avatars};
-use crate::fetch::fetch_avatars;
-use crate::switch::switch_avatar;
+use crate::auth::try_make_configuration_with_cookies;
+use crate::db::{AliasChange, Origin, Selector, create_alias_db, get_all_avatars};
+use crate::doctor::{AliasCheck, AliasHealth};
+use crate::export::export_html;
+use crate::fetch::{fetch_avatars, fetch_favorites};
+use crate::filter::filter_by_platform;
+use crate::output::{
+    AliasChangeRecord, AliasRecord, AuthCheckRecord, AvatarRecord, FavoriteGroupRecord,
+    FavoriteRecord, GroupRecord, OutputFormat, PresetRecord, StatusRecord, SwitchRecord, TagRecord,
+    print_record, print_records,
+};
+use crate::random::{RandomWeight, pick_random_avatar};
+use crate::switch::{
+    Target, refresh_selector_aliases, resolve_selector, resolve_target, switch_and_record,
+};
 
 #[derive(Debug, Parser)]
 struct Cli {
+    #[arg(long, global = true, value_enum, help = "Output format")]
+    output: Option<OutputFormat>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Auth profile to use (cookies are kept per profile)"
+    )]
+    profile: Option<String>,
+
+    #[arg(short, long, global = true, action = ArgAction::Count, help = "More log output (-vv for HTTP traces)")]
+    verbose: u8,
+
+    // Subcommands use -q for --query, so only the long form works after the command.
+    #[arg(long, global = true, action = ArgAction::Count, help = "Less log output")]
+    quiet: u8,
+
+    #[arg(
+        short = 'q',
+        action = ArgAction::Count,
+        help = "Same as --quiet, before the command only"
+    )]
+    quiet_short: u8,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Also write logs to a daily rotating file in ./logs"
+    )]
+    log_file: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Debug, Subcommand)]
 enum AliasCommands {
-    #[command(group(ArgGroup::new("alias_set").required(true).args(["id", "query"])), about = "Set avatar name aliases")]
+    #[command(group(ArgGroup::new("alias_set").required(true).args(["id", "query", "by_name"])), about = "Set avatar name aliases")]
     Set {
         #[arg(short, long, help = "Alias name")]
         alias: String,
@@ -37,6 +109,39 @@ enum AliasCommands {
             help = "Query to search for avatar ID instead of providing directly"
         )]
         query: Option<String>,
+
+        #[arg(
+            long,
+            help = "Exact avatar name, looked up again at switch time instead of a fixed ID"
+        )]
+        by_name: Option<String>,
+
+        #[arg(long, help = "With --by-name, only avatars by this author")]
+        author: Option<String>,
+
+        #[arg(
+            long,
+            help = "With --query, keep the query and run it again at switch time instead of a fixed ID"
+        )]
+        save_query: bool,
+
+        #[arg(short, long, help = "Note to keep with the alias")]
+        description: Option<String>,
+
+        #[arg(long, help = "Overwrite the alias if it points at another avatar")]
+        force: bool,
+    },
+
+    #[command(about = "Rename an avatar name alias")]
+    Rename {
+        #[arg(help = "Current alias name")]
+        old: String,
+
+        #[arg(help = "New alias name")]
+        new: String,
+
+        #[arg(long, help = "Replace an existing alias with the new name")]
+        force: bool,
     },
 
     #[command(about = "Delete avatar name aliases")]
@@ -47,6 +152,338 @@ enum AliasCommands {
 
     #[command(about = "List all avatar name aliases")]
     List {},
+
+    #[command(about = "Find aliases whose avatar is gone or shared, and re-point them by name")]
+    Doctor {
+        #[arg(long, help = "Also ask the API whether each avatar still exists")]
+        api: bool,
+
+        #[arg(
+            long,
+            help = "Re-point broken aliases to the avatar with their recorded name"
+        )]
+        fix: bool,
+
+        #[arg(short, long, help = "With --fix, do not ask before each change")]
+        yes: bool,
+    },
+
+    #[command(about = "Write all aliases to stdout in a shareable format")]
+    Export {
+        #[arg(long, value_enum, default_value_t = AliasFileFormat::Json, help = "File format")]
+        format: AliasFileFormat,
+    },
+
+    #[command(about = "Import aliases from a JSON, TOML or CSV file")]
+    Import {
+        #[arg(help = "File to import")]
+        file: PathBuf,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "File format (guessed from the extension if omitted)"
+        )]
+        format: Option<AliasFileFormat>,
+
+        #[arg(
+            long,
+            conflicts_with = "replace",
+            help = "Add and update aliases, keeping the others (default)"
+        )]
+        merge: bool,
+
+        #[arg(long, help = "Also remove aliases that are not in the file")]
+        replace: bool,
+
+        #[arg(long, help = "Show what would change without changing anything")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TagCommands {
+    #[command(group(ArgGroup::new("tag_target").required(true).args(["id", "query", "alias"])), about = "Tag an avatar")]
+    Add {
+        #[arg(help = "Tag name")]
+        tag: String,
+
+        #[arg(short, long, help = "Avatar ID to tag")]
+        id: Option<String>,
+
+        #[arg(short, long, help = "Local database search query to find avatar")]
+        query: Option<String>,
+
+        #[arg(short, long, help = "Avatar name alias to tag")]
+        alias: Option<String>,
+    },
+
+    #[command(group(ArgGroup::new("tag_target").required(true).args(["id", "query", "alias"])), about = "Remove a tag from an avatar")]
+    Remove {
+        #[arg(help = "Tag name")]
+        tag: String,
+
+        #[arg(short, long, help = "Avatar ID to untag")]
+        id: Option<String>,
+
+        #[arg(short, long, help = "Local database search query to find avatar")]
+        query: Option<String>,
+
+        #[arg(short, long, help = "Avatar name alias to untag")]
+        alias: Option<String>,
+    },
+
+    #[command(about = "List tags, optionally only one tag's avatars")]
+    List {
+        #[arg(help = "Tag name")]
+        tag: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum GroupCommands {
+    #[command(about = "Add aliases to a group, creating it if needed")]
+    Add {
+        #[arg(help = "Group name")]
+        group: String,
+
+        #[arg(required = true, help = "Alias names")]
+        aliases: Vec<String>,
+    },
+
+    #[command(about = "Remove aliases from a group")]
+    Remove {
+        #[arg(help = "Group name")]
+        group: String,
+
+        #[arg(required = true, help = "Alias names")]
+        aliases: Vec<String>,
+    },
+
+    #[command(about = "Delete a group (the aliases themselves are kept)")]
+    Delete {
+        #[arg(help = "Group name")]
+        group: String,
+    },
+
+    #[command(about = "List groups, optionally only one group's aliases")]
+    List {
+        #[arg(help = "Group name")]
+        group: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum StatusCommands {
+    #[command(group(ArgGroup::new("status_set").required(true).multiple(true).args(["status", "description"])), about = "Change our status and status description")]
+    Set {
+        #[arg(short, long, value_enum, help = "Status")]
+        status: Option<user::Status>,
+
+        #[arg(short, long, help = "Status description")]
+        description: Option<String>,
+    },
+
+    #[command(about = "Show our status and status description")]
+    Show {},
+}
+
+#[derive(Debug, Subcommand)]
+enum PresetCommands {
+    #[command(about = "Save an avatar, status, status description and bio as a preset")]
+    Save {
+        #[arg(help = "Preset name")]
+        name: String,
+
+        #[arg(short, long, help = "Avatar alias or ID")]
+        avatar: Option<String>,
+
+        #[arg(short, long, value_enum, help = "Status")]
+        status: Option<user::Status>,
+
+        #[arg(short, long, help = "Status description")]
+        description: Option<String>,
+
+        #[arg(short, long, help = "Bio")]
+        bio: Option<String>,
+
+        #[arg(short, long, help = "Overwrite an existing preset")]
+        force: bool,
+    },
+
+    #[command(about = "Apply a preset, restoring the profile if the avatar switch fails")]
+    Apply {
+        #[arg(help = "Preset name")]
+        name: String,
+    },
+
+    #[command(about = "List presets")]
+    List {},
+
+    #[command(about = "Delete a preset")]
+    Delete {
+        #[arg(help = "Preset name")]
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum FavCommands {
+    #[command(group(ArgGroup::new("fav_target").required(true).args(["id", "query", "alias"])), about = "Add an avatar to a favourite group")]
+    Add {
+        #[arg(help = "Favourite group name or display name")]
+        group: String,
+
+        #[arg(short, long, help = "Avatar ID to favourite")]
+        id: Option<String>,
+
+        #[arg(short, long, help = "Local database search query to find avatar")]
+        query: Option<String>,
+
+        #[arg(short, long, help = "Avatar name alias to favourite")]
+        alias: Option<String>,
+    },
+
+    #[command(group(ArgGroup::new("fav_target").required(true).args(["id", "query", "alias"])), about = "Remove an avatar from favourites")]
+    Remove {
+        #[arg(short, long, help = "Avatar ID to unfavourite")]
+        id: Option<String>,
+
+        #[arg(short, long, help = "Local database search query to find avatar")]
+        query: Option<String>,
+
+        #[arg(short, long, help = "Avatar name alias to unfavourite")]
+        alias: Option<String>,
+    },
+
+    #[command(group(ArgGroup::new("fav_target").required(true).args(["id", "query", "alias"])), about = "Move a favourited avatar to another group")]
+    Move {
+        #[arg(help = "Favourite group name or display name to move to")]
+        group: String,
+
+        #[arg(short, long, help = "Avatar ID to move")]
+        id: Option<String>,
+
+        #[arg(short, long, help = "Local database search query to find avatar")]
+        query: Option<String>,
+
+        #[arg(short, long, help = "Avatar name alias to move")]
+        alias: Option<String>,
+    },
+
+    #[command(about = "List favourited avatars, optionally only one group's")]
+    List {
+        #[arg(help = "Favourite group name or display name")]
+        group: Option<String>,
+    },
+
+    #[command(about = "List avatar favourite groups")]
+    Groups {
+        #[command(subcommand)]
+        command: Option<FavGroupCommands>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum FavGroupCommands {
+    #[command(about = "Change a favourite group's display name")]
+    Rename {
+        #[arg(help = "Favourite group name or display name")]
+        group: String,
+
+        #[arg(help = "New display name")]
+        display_name: String,
+    },
+}
+
+#[derive(Debug, Args)]
+#[group(id = "selection", required = true, multiple = true)]
+struct AvatarSelection {
+    #[arg(short, long, help = "Avatar ID (repeatable)")]
+    id: Vec<String>,
+
+    #[arg(short, long, help = "Avatar name alias (repeatable)")]
+    alias: Vec<String>,
+
+    #[arg(short, long, help = "Our avatars whose name matches this local search")]
+    query: Option<String>,
+
+    #[arg(long, help = "Our avatars with this local tag")]
+    tag: Option<String>,
+
+    #[arg(long, help = "Our avatars aliased in this group")]
+    group: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum AvatarCommands {
+    #[command(group(ArgGroup::new("edit_target").required(true).args(["avatar", "from_file"])), about = "Edit name, description, tags or release status of our own avatars")]
+    Edit {
+        #[arg(help = "Avatar ID or alias")]
+        avatar: Option<String>,
+
+        #[arg(long, help = "New avatar name")]
+        name: Option<String>,
+
+        #[arg(long, help = "New description")]
+        description: Option<String>,
+
+        #[arg(
+            long,
+            help = "New comma separated author tags (replaces the author tags, keeps content and system tags)"
+        )]
+        tags: Option<String>,
+
+        #[arg(long, value_enum, help = "New release status")]
+        release_status: Option<avatar::Release>,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["name", "description", "tags", "release_status"],
+            help = "Apply the edits listed in a JSON or CSV file"
+        )]
+        from_file: Option<PathBuf>,
+
+        #[arg(long, help = "Only show what would change")]
+        dry_run: bool,
+
+        #[arg(short, long, help = "Do not ask before applying the changes")]
+        yes: bool,
+    },
+
+    #[command(about = "Make the selected avatars public")]
+    Publish {
+        #[command(flatten)]
+        selection: AvatarSelection,
+
+        #[arg(long, help = "Only show which avatars would change")]
+        dry_run: bool,
+
+        #[arg(short, long, help = "Do not ask before applying the changes")]
+        yes: bool,
+    },
+
+    #[command(about = "Make the selected avatars private")]
+    Unpublish {
+        #[command(flatten)]
+        selection: AvatarSelection,
+
+        #[arg(long, help = "Only show which avatars would change")]
+        dry_run: bool,
+
+        #[arg(short, long, help = "Do not ask before applying the changes")]
+        yes: bool,
+    },
+}
+
+#[cfg(unix)]
+#[derive(Debug, Subcommand)]
+enum AgentCommands {
+    #[command(about = "Run the agent in the foreground, keeping the API session open")]
+    Start {},
+
+    #[command(about = "Check whether an agent is running")]
+    Status {},
 }
 
 #[derive(Debug, Subcommand)]
@@ -64,199 +501,1471 @@ enum AuthCommands {
     Check {},
 }
 
+#[derive(Debug, Subcommand)]
+enum ConfigCommands {
+    #[command(about = "Show a config value, or all of them")]
+    Get {
+        #[arg(help = "Config key")]
+        key: Option<String>,
+    },
+
+    #[command(about = "Write a config value to the config file")]
+    Set {
+        #[arg(help = "Config key")]
+        key: String,
+
+        #[arg(help = "New value")]
+        value: String,
+    },
+
+    #[command(about = "Open the config file in $EDITOR")]
+    Edit {},
+
+    #[command(about = "Show the config file path")]
+    Path {},
+}
+
+#[derive(Debug, Subcommand)]
+enum ExportCommands {
+    #[command(about = "Generate a static HTML gallery of the local database")]
+    Html {
+        #[arg(help = "Output directory")]
+        dir: PathBuf,
+
+        #[arg(long, help = "Do not download thumbnails (no auth cookie needed)")]
+        skip_thumbnails: bool,
+
+        #[arg(long, help = "Only avatars with this local tag")]
+        tag: Option<String>,
+
+        #[arg(long, help = "Only avatars aliased in this group")]
+        group: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum StatsView {
+    /// Switch count, last use and time worn per avatar
+    Avatars,
+    /// Avatars never switched to
+    NeverUsed,
+    /// Switches per weekday
+    Weekday,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SortOrder {
+    /// Alphabetical by avatar name
+    Name,
+    /// Most frequently and recently worn first
+    Frecency,
+}
+
 #[derive(Debug, Subcommand)]
 enum Commands {
+    #[cfg(unix)]
+    #[command(about = "Background agent that makes switches faster")]
+    Agent {
+        #[command(subcommand)]
+        command: AgentCommands,
+    },
+
     #[command(about = "Manage avatar name aliases")]
     Alias {
         #[command(subcommand)]
         command: AliasCommands,
     },
 
-    #[command(about = "Manage authentication")]
-    Auth {
-        #[command(subcommand)]
-        command: AuthCommands,
-    },
+    #[command(about = "Edit our own uploaded avatars")]
+    Avatar {
+        #[command(subcommand)]
+        command: AvatarCommands,
+    },
+
+    #[command(about = "Manage authentication")]
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+
+    #[command(about = "Manage configuration")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    #[command(about = "Show the avatar we are wearing right now")]
+    Current {
+        #[arg(short, long, help = "Keep polling and show each change made in-game")]
+        watch: bool,
+
+        #[arg(
+            long,
+            default_value_t = 30,
+            help = "Seconds between polls with --watch"
+        )]
+        interval: u64,
+    },
+
+    #[command(about = "Run scheduled avatar rotation in the foreground")]
+    Daemon {
+        #[arg(short, long, default_value = "./schedule.toml", help = "Schedule file")]
+        schedule: PathBuf,
+
+        #[arg(
+            long,
+            help = "Also follow in-game avatar changes and record them to history"
+        )]
+        listen: bool,
+    },
+
+    #[command(about = "Follow in-game avatar changes over the pipeline and record them to history")]
+    Listen {},
+
+    #[command(about = "Export local database")]
+    Export {
+        #[command(subcommand)]
+        command: ExportCommands,
+    },
+
+    #[command(about = "Manage local avatar tags")]
+    Tag {
+        #[command(subcommand)]
+        command: TagCommands,
+    },
+
+    #[command(about = "Show or change our status")]
+    Status {
+        #[command(subcommand)]
+        command: StatusCommands,
+    },
+
+    #[command(about = "Manage presets of avatar, status and bio")]
+    Preset {
+        #[command(subcommand)]
+        command: PresetCommands,
+    },
+
+    #[command(about = "Manage named groups of aliases")]
+    Group {
+        #[command(subcommand)]
+        command: GroupCommands,
+    },
+
+    #[command(about = "Manage VRChat avatar favourites")]
+    Fav {
+        #[command(subcommand)]
+        command: FavCommands,
+    },
+
+    #[command(about = "Fetch avatars to local database")]
+    Fetch {
+        #[arg(long, help = "Also sync favourited avatars and favourite groups")]
+        favorites: bool,
+    },
+
+    #[command(group(ArgGroup::new("switch_method").required(true).args(["id", "query", "alias", "back", "random"])), about = "Change avatar")]
+    Switch {
+        #[arg(short, long, help = "Avatar ID to switch to")]
+        id: Option<String>,
+
+        #[arg(short, long, help = "Local database search query to find avatar")]
+        query: Option<String>,
+
+        #[arg(short, long, help = "Avatar name alias to switch to")]
+        alias: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            num_args = 0..=1,
+            default_missing_value = "1",
+            help = "Go back to the avatar worn N switches ago (default 1)"
+        )]
+        back: Option<usize>,
+
+        #[arg(
+            short,
+            long,
+            help = "Switch to a random avatar from the local database"
+        )]
+        random: bool,
+
+        #[arg(
+            long,
+            help = "With --random, only pick avatars built for this platform (\"all\" to disable the default)"
+        )]
+        platform: Option<String>,
+
+        #[arg(long, help = "With --random, only pick avatars with this local tag")]
+        tag: Option<String>,
+
+        #[arg(long, help = "With --random, only pick avatars aliased in this group")]
+        group: Option<String>,
+
+        #[arg(
+            long,
+            default_value_t = 0,
+            help = "With --random, do not pick any of the N most recently worn avatars"
+        )]
+        exclude_recent: usize,
+
+        #[arg(long, value_enum, default_value_t = RandomWeight::None, help = "With --random, how to weight the pick")]
+        weight: RandomWeight,
+
+        #[arg(
+            long,
+            help = "With --random, also pick favourited and cached avatars of other people"
+        )]
+        include_others: bool,
+    },
+
+    #[command(about = "Serve a localhost HTTP API for switching avatars")]
+    Serve {
+        #[arg(
+            short,
+            long,
+            default_value_t = 8787,
+            help = "Port to listen on (127.0.0.1 only)"
+        )]
+        port: u16,
+
+        #[arg(
+            long,
+            help = "Bearer token clients must send (default: VAVC_SERVE_TOKEN or ./serve-token)"
+        )]
+        token: Option<String>,
+    },
+
+    #[command(about = "Show avatar switch history")]
+    History {
+        #[arg(
+            short = 'n',
+            long,
+            default_value_t = 20,
+            help = "Number of entries to show"
+        )]
+        limit: usize,
+    },
+
+    #[command(about = "Search for avatars in local database")]
+    Search {
+        #[arg(short, long, help = "Search query")]
+        query: String,
+
+        #[arg(
+            long,
+            help = "Only avatars built for this platform (\"all\" to disable the default)"
+        )]
+        platform: Option<String>,
+
+        #[arg(long, help = "Only avatars with this local tag")]
+        tag: Option<String>,
+
+        #[arg(long, help = "Only avatars aliased in this group")]
+        group: Option<String>,
+
+        #[arg(long, value_enum, help = "Sort order")]
+        sort: Option<SortOrder>,
+    },
+
+    #[command(about = "Show all avatars in local database")]
+    List {
+        #[arg(
+            long,
+            help = "Only avatars built for this platform (\"all\" to disable the default)"
+        )]
+        platform: Option<String>,
+
+        #[arg(long, help = "Only avatars with this local tag")]
+        tag: Option<String>,
+
+        #[arg(long, help = "Only avatars aliased in this group")]
+        group: Option<String>,
+
+        #[arg(long, value_enum, help = "Sort order")]
+        sort: Option<SortOrder>,
+    },
+
+    #[command(about = "Show avatar usage statistics from switch history")]
+    Stats {
+        #[arg(long, value_enum, default_value_t = StatsView::Avatars, help = "What to show")]
+        view: StatsView,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let _log_guard = log::init(cli.verbose, cli.quiet + cli.quiet_short, cli.log_file);
+
+    let mut settings = match config::load() {
+        Ok(settings) => settings,
+        Err(e) if matches!(cli.command, Commands::Config { .. }) => {
+            eprintln!("{}", e);
+            config::Config::default()
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(output) = cli.output {
+        settings.default_output = output;
+    }
+    if let Some(profile) = cli.profile {
+        settings.default_profile = profile;
+    }
+    config::init(settings);
+    let output = config::current().default_output;
+
+    match cli.command {
+        #[cfg(unix)]
+        Commands::Agent { command } => match command {
+            AgentCommands::Start {} => {
+                if let Err(e) = agent::run_agent(make_configuration_with_cookies()).await {
+                    eprintln!("Error running agent: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            AgentCommands::Status {} => {
+                if agent::ping().await {
+                    println!("Agent is running on {}", agent::SOCKET_PATH);
+                } else {
+                    println!("Agent is not running");
+                    std::process::exit(1);
+                }
+            }
+        },
+
+        Commands::Alias { command } => match command {
+            AliasCommands::Set {
+                alias,
+                id: avatar_id,
+                query,
+                by_name,
+                author,
+                save_query,
+                description,
+                force,
+            } => {
+                if let Err(e) = create_alias_db() {
+                    eprintln!("Error opening/creating alias database: {}", e);
+                    std::process::exit(1);
+                }
+
+                if author.is_some() && by_name.is_none() {
+                    eprintln!("--author can only be used with --by-name");
+                    std::process::exit(1);
+                }
+                if save_query && query.is_none() {
+                    eprintln!("--save-query can only be used with --query");
+                    std::process::exit(1);
+                }
+
+                let (target, selector) = match (avatar_id, query, by_name) {
+                    (Some(avatar_id), _, _) => (Target::Id(avatar_id), None),
+                    (_, Some(query), _) if save_query => {
+                        (Target::Query(query.clone()), Some(Selector::Query(query)))
+                    }
+                    (_, Some(query), _) => (Target::Query(query), None),
+                    (_, _, Some(name)) => (
+                        Target::Query(name.clone()),
+                        Some(Selector::Name { name, author }),
+                    ),
+                    _ => unreachable!("clap requires one of --id, --query or --by-name"),
+                };
+                let resolved = match &selector {
+                    Some(selector) => resolve_selector(selector).map(|avatar| avatar.id),
+                    None => resolve_target(&target),
+                };
+                let avatar_id = match resolved {
+                    Ok(avatar_id) => avatar_id,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                };
+
+                let warning = match db::get_avatar_by_id(&avatar_id) {
+                    Ok(Some(_)) => None,
+                    Ok(None) => match try_make_configuration_with_cookies() {
+                        Some(config) => avatar::lookup_avatar(&config, &avatar_id)
+                            .await
+                            .err()
+                            .map(|e| {
+                                format!(
+                                    "Avatar ID '{}' is not in the local database and could not be looked up: {}",
+                                    avatar_id, e
+                                )
+                            }),
+                        None => Some(format!(
+                            "Avatar ID '{}' is not in the local database",
+                            avatar_id
+                        )),
+                    },
+                    Err(e) => Some(format!("Could not check the avatar ID: {}", e)),
+                };
+                if let Some(warning) = &warning {
+                    warn!("{}", warning);
+                }
+
+                let previous_avatar_id = match db::get_alias(&alias) {
+                    Ok(previous) => previous.map(|previous| previous.avatar_id),
+                    Err(e) => {
+                        eprintln!("Error retrieving alias: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let result = match db::register_alias(
+                    &alias,
+                    &avatar_id,
+                    selector.as_ref(),
+                    description.as_deref(),
+                    force,
+                ) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        eprintln!("Error registering alias: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                if result == AliasChange::Exists {
+                    warn!(
+                        "Alias '{}' already points at another avatar; use --force to overwrite it",
+                        alias
+                    );
+                }
+
+                print_alias_change(
+                    output,
+                    AliasChangeRecord {
+                        alias,
+                        result,
+                        avatar_id: Some(avatar_id),
+                        previous_avatar_id,
+                        warning,
+                    },
+                );
+            }
+
+            AliasCommands::Rename { old, new, force } => {
+                if let Err(e) = create_alias_db() {
+                    eprintln!("Error opening/creating alias database: {}", e);
+                    std::process::exit(1);
+                }
+
+                let avatar_id = match db::get_alias(&old) {
+                    Ok(alias) => alias.map(|alias| alias.avatar_id),
+                    Err(e) => {
+                        eprintln!("Error retrieving alias: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let result = match db::rename_alias(&old, &new, force) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        eprintln!("Error renaming alias: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let warning = match result {
+                    AliasChange::NotFound => Some(format!("Alias '{}' does not exist", old)),
+                    AliasChange::Exists => Some(format!(
+                        "Alias '{}' already exists; use --force to replace it",
+                        new
+                    )),
+                    _ => None,
+                };
+
+                print_alias_change(
+                    output,
+                    AliasChangeRecord {
+                        alias: new,
+                        result,
+                        avatar_id,
+                        previous_avatar_id: None,
+                        warning,
+                    },
+                );
+            }
+
+            AliasCommands::Delete { alias } => {
+                if let Err(e) = create_alias_db() {
+                    eprintln!("Error opening/creating alias database: {}", e);
+                    std::process::exit(1);
+                }
+
+                let previous_avatar_id = match db::get_alias(&alias) {
+                    Ok(previous) => previous.map(|previous| previous.avatar_id),
+                    Err(e) => {
+                        eprintln!("Error retrieving alias: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let result = match db::remove_alias(&alias) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        eprintln!("Error removing alias: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+
+                print_alias_change(
+                    output,
+                    AliasChangeRecord {
+                        alias,
+                        result,
+                        avatar_id: None,
+                        previous_avatar_id,
+                        warning: None,
+                    },
+                );
+            }
+
+            AliasCommands::List {} => {
+                if let Err(e) = create_alias_db() {
+                    eprintln!("Error opening/creating alias database: {}", e);
+                    std::process::exit(1);
+                }
+
+                match db::get_all_aliases() {
+                    Ok(aliases) => {
+                        let records: Vec<AliasRecord> =
+                            aliases.into_iter().map(AliasRecord::from).collect();
+                        print_records(output, &records);
+
+                        info!("Total aliases: {}", &records.len());
+                    }
+                    Err(e) => {
+                        eprintln!("Error retrieving aliases from database: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            AliasCommands::Doctor { api, fix, yes } => {
+                let config = api.then(make_configuration_with_cookies);
+                let checks = match doctor::check_aliases(config.as_ref(), fix).await {
+                    Ok(checks) => checks,
+                    Err(e) => {
+                        eprintln!("Error checking aliases: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                print_records(output, &checks);
+
+                let (fixable, unfixable): (Vec<&AliasCheck>, Vec<&AliasCheck>) = checks
+                    .iter()
+                    .filter(|check| {
+                        matches!(check.status, AliasHealth::Missing | AliasHealth::Deleted)
+                    })
+                    .partition(|check| check.suggestion.is_some());
+                info!(
+                    "{} of {} aliases are broken",
+                    fixable.len() + unfixable.len(),
+                    checks.len()
+                );
+
+                if !fix {
+                    if !fixable.is_empty() {
+                        info!(
+                            "Run with --fix to re-point {} of them by name",
+                            fixable.len()
+                        );
+                    }
+                    return;
+                }
+
+                for check in unfixable {
+                    warn!(
+                        "Cannot fix alias '{}': {}",
+                        check.alias,
+                        check
+                            .note
+                            .as_deref()
+                            .unwrap_or("no avatar to re-point it at")
+                    );
+                }
+
+                for check in fixable {
+                    let avatar_id = check.suggestion.as_deref().unwrap_or_default();
+                    if !yes {
+                        let answer = read_user_input(&format!(
+                            "Re-point '{}' to {} ({})? [y/N] ",
+                            check.alias,
+                            check.avatar_name.as_deref().unwrap_or_default(),
+                            avatar_id
+                        ));
+                        if !answer.eq_ignore_ascii_case("y") {
+                            continue;
+                        }
+                    }
+
+                    match db::register_alias(&check.alias, avatar_id, None, None, true) {
+                        Ok(_) => info!("Re-pointed '{}' to {}", check.alias, avatar_id),
+                        Err(e) => {
+                            eprintln!("Error re-pointing alias '{}': {}", check.alias, e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+
+            AliasCommands::Export { format } => match aliasfile::export_aliases(format) {
+                Ok(content) => print!("{}", content),
+                Err(e) => {
+                    eprintln!("Error exporting aliases: {}", e);
+                    std::process::exit(1);
+                }
+            },
+
+            AliasCommands::Import {
+                file,
+                format,
+                merge: _,
+                replace,
+                dry_run,
+            } => {
+                let Some(format) = format.or_else(|| AliasFileFormat::from_path(&file)) else {
+                    eprintln!(
+                        "Cannot tell the format of {}; pass --format",
+                        file.display()
+                    );
+                    std::process::exit(1);
+                };
+                let entries = match aliasfile::read_alias_file(&file, format) {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        eprintln!("Error reading {}: {}", file.display(), e);
+                        std::process::exit(1);
+                    }
+                };
+                let steps = match aliasfile::plan_import(entries, replace) {
+                    Ok(steps) => steps,
+                    Err(e) => {
+                        eprintln!("Error comparing with the alias database: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+
+                if dry_run {
+                    info!("Dry run, no changes made");
+                } else if let Err(e) = aliasfile::apply_import(&steps) {
+                    eprintln!("Error importing aliases: {}", e);
+                    std::process::exit(1);
+                }
+
+                let records: Vec<&AliasChangeRecord> =
+                    steps.iter().map(|step| &step.record).collect();
+                print_records(output, &records);
+
+                if records
+                    .iter()
+                    .any(|record| record.result == AliasChange::NotFound)
+                {
+                    std::process::exit(1);
+                }
+            }
+        },
+
+        Commands::Auth { command } => match command {
+            AuthCommands::New { username, password } => {
+                get_new_auth_cookie(username, password).await
+            }
+            AuthCommands::Check {} => {
+                let result = check_auth_cookie().await;
+                print_record(
+                    output,
+                    &AuthCheckRecord {
+                        valid: result.is_ok(),
+                        error: result.err().map(|e| e.to_string()),
+                    },
+                );
+            }
+        },
+
+        Commands::Avatar { command } => match command {
+            AvatarCommands::Edit {
+                avatar,
+                name,
+                description,
+                tags,
+                release_status,
+                from_file,
+                dry_run,
+                yes,
+            } => {
+                let edits = match (from_file, avatar) {
+                    (Some(file), _) => match avatar::read_edit_file(&file) {
+                        Ok(edits) => edits,
+                        Err(e) => {
+                            eprintln!("Error reading {}: {}", file.display(), e);
+                            std::process::exit(1);
+                        }
+                    },
+                    (None, Some(avatar)) => vec![avatar::AvatarEdit {
+                        avatar,
+                        name,
+                        description,
+                        tags,
+                        release_status,
+                    }],
+                    (None, None) => unreachable!("clap requires an avatar or --from-file"),
+                };
+                if edits.iter().all(|edit| edit.is_empty()) {
+                    eprintln!(
+                        "Nothing to edit; give --name, --description, --tags or --release-status"
+                    );
+                    std::process::exit(1);
+                }
+
+                let config = make_configuration_with_cookies();
+                let mut plans = Vec::new();
+                for edit in edits.iter().filter(|edit| !edit.is_empty()) {
+                    match avatar::plan_edit(&config, edit).await {
+                        Ok(plan) => plans.push(plan),
+                        Err(e) => {
+                            eprintln!("{}: {}", edit.avatar, e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+
+                let changes: Vec<&avatar::AvatarChange> =
+                    plans.iter().flat_map(|plan| &plan.changes).collect();
+                if changes.is_empty() {
+                    info!("Nothing to change");
+                    return;
+                }
+                print_records(output, &changes);
+
+                let edited = plans.iter().filter(|plan| !plan.changes.is_empty()).count();
+                if dry_run {
+                    info!("Dry run, {} avatar(s) would change", edited);
+                    return;
+                }
+                if !yes {
+                    let answer = read_user_input(&format!(
+                        "Apply {} change(s) to {} avatar(s)? [y/N] ",
+                        changes.len(),
+                        edited
+                    ));
+                    if !answer.eq_ignore_ascii_case("y") {
+                        return;
+                    }
+                }
+
+                let mut failed = 0;
+                for plan in plans {
+                    if let Err(e) = avatar::apply_edit(&config, plan).await {
+                        eprintln!("{}", e);
+                        failed += 1;
+                    }
+                }
+                if failed > 0 {
+                    eprintln!("{} of {} avatar(s) could not be updated", failed, edited);
+                    std::process::exit(1);
+                }
+            }
+
+            AvatarCommands::Publish {
+                selection,
+                dry_run,
+                yes,
+            } => set_release_status(output, selection, avatar::Release::Public, dry_run, yes).await,
+
+            AvatarCommands::Unpublish {
+                selection,
+                dry_run,
+                yes,
+            } => {
+                set_release_status(output, selection, avatar::Release::Private, dry_run, yes).await
+            }
+        },
+
+        Commands::Config { command } => match command {
+            ConfigCommands::Get { key: Some(key) } => match config::get(config::current(), &key) {
+                Ok(value) => println!("{}", value),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            },
+            ConfigCommands::Get { key: None } => {
+                for key in config::keys() {
+                    let value = config::get(config::current(), key).unwrap_or_default();
+                    println!("{} = {}", key, value);
+                }
+            }
+            ConfigCommands::Set { key, value } => {
+                if let Err(e) = config::set(&key, &value) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            ConfigCommands::Edit {} => {
+                let editor = std::env::var("EDITOR").unwrap_or_else(|_| String::from("vi"));
+                match std::process::Command::new(&editor)
+                    .arg(config::config_path())
+                    .status()
+                {
+                    Ok(_) => {
+                        if let Err(e) = config::load() {
+                            eprintln!("Warning: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to launch editor '{}': {}", editor, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            ConfigCommands::Path {} => println!("{}", config::config_path().display()),
+        },
+
+        Commands::Current { watch, interval } => {
+            let config = make_configuration_with_cookies();
+            if watch {
+                current::watch_current(&config, output, Duration::from_secs(interval.max(1))).await;
+                return;
+            }
+
+            match current::get_current(&config).await {
+                Ok(record) => print_record(output, &record),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Daemon { schedule, listen } => {
+            let schedule = match daemon::load_schedule(&schedule) {
+                Ok(schedule) => schedule,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            // Enforcing needs to hear about in-game changes as well.
+            let pipeline_url = (listen || schedule.enforce.is_some()).then(checked_pipeline_url);
+
+            if let Err(e) =
+                daemon::run_daemon(make_configuration_with_cookies(), schedule, pipeline_url).await
+            {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Listen {} => {
+            let url = checked_pipeline_url();
+            let config = make_configuration_with_cookies();
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            tokio::spawn(pipeline::listen(config, url, tx));
+            let ctrl_c = tokio::signal::ctrl_c();
+            tokio::pin!(ctrl_c);
+
+            loop {
+                tokio::select! {
+                    change = rx.recv() => match change {
+                        Some(avatar_id) => print_record(
+                            output,
+                            &SwitchRecord {
+                                avatar_id,
+                                resolved_by: String::from("in-game"),
+                                input: String::from("pipeline"),
+                                success: true,
+                                vetoed: false,
+                                error: None,
+                            },
+                        ),
+                        None => {
+                            eprintln!("The pipeline listener stopped unexpectedly");
+                            std::process::exit(1);
+                        }
+                    },
+                    _ = &mut ctrl_c => break,
+                }
+            }
+        }
+
+        Commands::Export { command } => match command {
+            ExportCommands::Html {
+                dir,
+                skip_thumbnails,
+                tag,
+                group,
+            } => {
+                let avatars = match db::create_avatar_db().and_then(|_| get_all_avatars()) {
+                    Ok(avatars) => filter_by_tag_and_group(avatars, tag, group),
+                    Err(e) => {
+                        eprintln!("Error retrieving avatars from database: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let config = if skip_thumbnails {
+                    None
+                } else {
+                    Some(make_configuration_with_cookies())
+                };
+
+                if let Err(e) = export_html(config, &dir, avatars).await {
+                    eprintln!("Error exporting HTML gallery: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
+
+        Commands::Tag { command } => match command {
+            TagCommands::Add {
+                tag,
+                id,
+                query,
+                alias,
+            } => {
+                let avatar_id = resolve_avatar_args(id, query, alias);
+                match db::add_tag(&avatar_id, &tag) {
+                    Ok(true) => info!("Tagged {} as '{}'", avatar_id, tag),
+                    Ok(false) => info!("{} is already tagged '{}'", avatar_id, tag),
+                    Err(e) => {
+                        eprintln!("Error adding tag: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            TagCommands::Remove {
+                tag,
+                id,
+                query,
+                alias,
+            } => {
+                let avatar_id = resolve_avatar_args(id, query, alias);
+                match db::remove_tag(&avatar_id, &tag) {
+                    Ok(true) => info!("Removed tag '{}' from {}", tag, avatar_id),
+                    Ok(false) => warn!("{} is not tagged '{}'", avatar_id, tag),
+                    Err(e) => {
+                        eprintln!("Error removing tag: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            TagCommands::List { tag: filter } => {
+                let tags = match db::get_all_tags() {
+                    Ok(tags) => tags,
+                    Err(e) => {
+                        eprintln!("Error retrieving tags from database: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let names: HashMap<String, String> = get_all_avatars()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|avatar| (avatar.id, avatar.name))
+                    .collect();
+
+                let records: Vec<TagRecord> = tags
+                    .into_iter()
+                    .filter(|(_, tag)| filter.as_ref().is_none_or(|filter| filter == tag))
+                    .map(|(avatar_id, tag)| TagRecord {
+                        tag,
+                        avatar_name: names.get(&avatar_id).cloned(),
+                        avatar_id,
+                    })
+                    .collect();
+                print_records(output, &records);
 
-    #[command(about = "Fetch avatars to local database")]
-    Fetch {},
+                info!("Total tagged avatars: {}", &records.len());
+            }
+        },
 
-    #[command(group(ArgGroup::new("switch_method").required(true).args(["id", "query", "alias"])), about = "Change avatar")]
-    Switch {
-        #[arg(short, long, help = "Avatar ID to switch to")]
-        id: Option<String>,
+        Commands::Status { command } => {
+            let config = make_configuration_with_cookies();
+            let user = match switch::get_current_user(&config).await {
+                Ok(user) => user,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
 
-        #[arg(short, long, help = "Local database search query to find avatar")]
-        query: Option<String>,
+            let user = match command {
+                StatusCommands::Set {
+                    status,
+                    description,
+                } => match user::update_profile(
+                    &config,
+                    &user.id,
+                    status.map(Into::into),
+                    description,
+                    None,
+                )
+                .await
+                {
+                    Ok(user) => user,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                },
+                StatusCommands::Show {} => user,
+            };
+            print_record(
+                output,
+                &StatusRecord {
+                    status: user::status_name(user.status),
+                    status_description: user.status_description,
+                },
+            );
+        }
 
-        #[arg(short, long, help = "Avatar name alias to switch to")]
-        alias: Option<String>,
-    },
+        Commands::Preset { command } => match command {
+            PresetCommands::Save {
+                name,
+                avatar,
+                status,
+                description,
+                bio,
+                force,
+            } => {
+                if avatar.is_none() && status.is_none() && description.is_none() && bio.is_none() {
+                    eprintln!("Nothing to save; give --avatar, --status, --description or --bio");
+                    std::process::exit(1);
+                }
+                if let Some(avatar) = &avatar
+                    && let Err(e) = resolve_target(&Target::alias_or_id(avatar))
+                {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
 
-    #[command(about = "Search for avatars in local database")]
-    Search {
-        #[arg(short, long, help = "Search query")]
-        query: String,
-    },
+                let exists = match db::get_preset(&name) {
+                    Ok(existing) => existing.is_some(),
+                    Err(e) => {
+                        eprintln!("Error retrieving preset: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                if exists && !force {
+                    eprintln!("Preset '{}' already exists; use --force to overwrite", name);
+                    std::process::exit(1);
+                }
 
-    #[command(about = "Show all avatars in local database")]
-    List {},
-}
+                let preset = db::Preset {
+                    name,
+                    avatar,
+                    status: status.map(|status| status.name().to_string()),
+                    status_description: description,
+                    bio,
+                };
+                match db::save_preset(&preset) {
+                    Ok(()) if exists => info!("Updated preset '{}'", preset.name),
+                    Ok(()) => info!("Saved preset '{}'", preset.name),
+                    Err(e) => {
+                        eprintln!("Error saving preset: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
 
-#[tokio::main]
-async fn main() {
-    let cli = Cli::parse();
+            PresetCommands::Apply { name } => {
+                let preset = match db::get_preset(&name) {
+                    Ok(Some(preset)) => preset,
+                    Ok(None) => {
+                        eprintln!("No preset named '{}'", name);
+                        std::process::exit(1);
+                    }
+                    Err(e) => {
+                        eprintln!("Error retrieving preset: {}", e);
+                        std::process::exit(1);
+                    }
+                };
 
-    match cli.command {
-        Commands::Alias { command } => match command {
-            AliasCommands::Set {
-                alias,
-                id: avatar_id,
-                query,
-            } => {
+                let config = make_configuration_with_cookies();
+                if let Err(e) = preset::apply_preset(&config, &preset).await {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            PresetCommands::List {} => match db::get_all_presets() {
+                Ok(presets) => {
+                    let records: Vec<PresetRecord> =
+                        presets.into_iter().map(PresetRecord::from).collect();
+                    print_records(output, &records);
+                }
+                Err(e) => {
+                    eprintln!("Error retrieving presets from database: {}", e);
+                    std::process::exit(1);
+                }
+            },
+
+            PresetCommands::Delete { name } => match db::delete_preset(&name) {
+                Ok(true) => info!("Deleted preset '{}'", name),
+                Ok(false) => warn!("Preset '{}' does not exist", name),
+                Err(e) => {
+                    eprintln!("Error deleting preset: {}", e);
+                    std::process::exit(1);
+                }
+            },
+        },
+
+        Commands::Group { command } => match command {
+            GroupCommands::Add { group, aliases } => {
                 if let Err(e) = create_alias_db() {
                     eprintln!("Error opening/creating alias database: {}", e);
                     std::process::exit(1);
                 }
 
-                if let Some(avatar_id) = avatar_id {
-                    if let Err(e) = db::register_alias(&alias, &avatar_id) {
-                        eprintln!("Error registering alias: {}", e);
-                        return;
+                for alias in aliases {
+                    if let Ok(None) = db::get_alias(&alias) {
+                        warn!("Alias '{}' does not exist yet", alias);
+                    }
+                    match db::add_to_group(&group, &alias) {
+                        Ok(true) => info!("Added '{}' to group '{}'", alias, group),
+                        Ok(false) => info!("'{}' is already in group '{}'", alias, group),
+                        Err(e) => {
+                            eprintln!("Error adding to group: {}", e);
+                            std::process::exit(1);
+                        }
                     }
-                    return;
                 }
+            }
 
-                if let Some(query) = query {
-                    match db::get_avatar_first_hit_by_name(&query) {
-                        Ok(avatar) => {
-                            if let Some(avatar) = avatar {
-                                println!("Found avatar: {} ({})", avatar.name, avatar.id);
-                                if let Err(e) = db::register_alias(&alias, &avatar.id) {
-                                    eprintln!("Error registering alias: {}", e);
-                                    return;
-                                }
-                            } else {
-                                eprintln!("No avatar found matching query '{}'", query);
-                            }
-                        }
+            GroupCommands::Remove { group, aliases } => {
+                for alias in aliases {
+                    match db::remove_from_group(&group, &alias) {
+                        Ok(true) => info!("Removed '{}' from group '{}'", alias, group),
+                        Ok(false) => warn!("'{}' is not in group '{}'", alias, group),
                         Err(e) => {
-                            eprintln!("Error retrieving avatar for query '{}': {}", query, e);
-                            return;
+                            eprintln!("Error removing from group: {}", e);
+                            std::process::exit(1);
                         }
                     }
-                    return;
                 }
             }
 
-            AliasCommands::Delete { alias } => {
-                if let Err(e) = create_alias_db() {
-                    eprintln!("Error opening/creating alias database: {}", e);
+            GroupCommands::Delete { group } => match db::delete_group(&group) {
+                Ok(0) => warn!("Group '{}' does not exist", group),
+                Ok(count) => info!("Deleted group '{}' ({} aliases)", group, count),
+                Err(e) => {
+                    eprintln!("Error deleting group: {}", e);
                     std::process::exit(1);
                 }
+            },
 
-                if let Err(e) = db::remove_alias(&alias) {
-                    eprintln!("Error removing alias: {}", e);
-                    return;
+            GroupCommands::List { group: filter } => match db::get_all_group_members() {
+                Ok(members) => {
+                    let records: Vec<GroupRecord> = members
+                        .into_iter()
+                        .filter(|(group, _, _)| {
+                            filter.as_ref().is_none_or(|filter| filter == group)
+                        })
+                        .map(|(group, alias, avatar_id)| GroupRecord {
+                            group,
+                            alias,
+                            avatar_id,
+                        })
+                        .collect();
+                    print_records(output, &records);
+
+                    info!("Total group members: {}", &records.len());
+                }
+                Err(e) => {
+                    eprintln!("Error retrieving groups from database: {}", e);
+                    std::process::exit(1);
+                }
+            },
+        },
+
+        Commands::Fav { command } => match command {
+            FavCommands::Add {
+                group,
+                id,
+                query,
+                alias,
+            } => {
+                let avatar_id = resolve_avatar_args(id, query, alias);
+                let config = make_configuration_with_cookies();
+                if let Err(e) = favorites::add_favorite(&config, &avatar_id, &group).await {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
                 }
             }
 
-            AliasCommands::List {} => {
-                if let Err(e) = create_alias_db() {
-                    eprintln!("Error opening/creating alias database: {}", e);
+            FavCommands::Remove { id, query, alias } => {
+                let avatar_id = resolve_avatar_args(id, query, alias);
+                let config = make_configuration_with_cookies();
+                if let Err(e) = favorites::remove_favorite(&config, &avatar_id).await {
+                    eprintln!("{}", e);
                     std::process::exit(1);
                 }
+            }
 
-                match db::get_all_aliases() {
-                    Ok(aliases) => {
-                        for (name, avatar_id) in &aliases {
-                            println!("{}: {}", name, avatar_id);
-                        }
+            FavCommands::Move {
+                group,
+                id,
+                query,
+                alias,
+            } => {
+                let avatar_id = resolve_avatar_args(id, query, alias);
+                let config = make_configuration_with_cookies();
+                if let Err(e) = favorites::move_favorite(&config, &avatar_id, &group).await {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            FavCommands::List { group } => {
+                let group = group.map(|group| match favorites::find_group(&group) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                });
+
+                match db::get_favorites(group.as_deref()) {
+                    Ok(favorites) => {
+                        let records: Vec<FavoriteRecord> = favorites
+                            .into_iter()
+                            .map(|(group, avatar_id, avatar_name)| FavoriteRecord {
+                                group,
+                                avatar_id,
+                                avatar_name,
+                            })
+                            .collect();
+                        print_records(output, &records);
 
-                        println!();
-                        println!("Total aliases: {}", &aliases.len());
+                        info!("Total favourites: {}", &records.len());
                     }
                     Err(e) => {
-                        eprintln!("Error retrieving aliases from database: {}", e);
+                        eprintln!("Error retrieving favourites from database: {}", e);
                         std::process::exit(1);
                     }
                 }
             }
-        },
 
-        Commands::Auth { command } => match command {
-            AuthCommands::New { username, password } => {
-                get_new_auth_cookie(username, password).await
+            FavCommands::Groups { command: None } => match db::get_favorite_groups() {
+                Ok(groups) => {
+                    let records: Vec<FavoriteGroupRecord> =
+                        groups.into_iter().map(FavoriteGroupRecord::from).collect();
+                    print_records(output, &records);
+                }
+                Err(e) => {
+                    eprintln!("Error retrieving favourite groups from database: {}", e);
+                    std::process::exit(1);
+                }
+            },
+
+            FavCommands::Groups {
+                command:
+                    Some(FavGroupCommands::Rename {
+                        group,
+                        display_name,
+                    }),
+            } => {
+                let config = make_configuration_with_cookies();
+                if let Err(e) = favorites::rename_group(&config, &group, &display_name).await {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
             }
-            AuthCommands::Check {} => check_auth_cookie().await,
         },
 
-        Commands::Fetch {} => {
-            let avatars = fetch_avatars(make_configuration_with_cookies()).await;
-            if let Err(e) = db::rebuild_avatar_db(avatars) {
+        Commands::Fetch { favorites } => {
+            let config = make_configuration_with_cookies();
+            let avatars = match fetch_avatars(config.clone()).await {
+                Ok(avatars) => avatars,
+                Err(e) => {
+                    eprintln!("{}; the avatar database was left as it was", e);
+                    std::process::exit(1);
+                }
+            };
+            let mut records: Vec<AvatarRecord> = avatars.iter().map(AvatarRecord::from).collect();
+            if let Err(e) = db::rebuild_avatar_db(avatars, Origin::Own) {
                 eprintln!("Error rebuilding avatar database: {}", e);
                 std::process::exit(1);
             }
 
-            println!("Avatar database updated successfully.");
+            if favorites {
+                let (groups, favorites, avatars) = match fetch_favorites(&config).await {
+                    Ok(fetched) => fetched,
+                    Err(e) => {
+                        eprintln!("Error syncing favourites: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                records.extend(avatars.iter().map(AvatarRecord::from));
+                if let Err(e) = db::rebuild_avatar_db(avatars, Origin::Favorite)
+                    .and_then(|_| db::replace_favorites(&groups, &favorites))
+                {
+                    eprintln!("Error storing favourites: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            if let Err(e) = refresh_selector_aliases() {
+                warn!("Failed to refresh selector aliases: {}", e);
+            }
+
+            print_records(output, &records);
+            info!("Avatar database updated successfully.");
         }
 
         Commands::Switch {
             id: avatar_id,
             query,
             alias,
+            back,
+            random,
+            platform,
+            tag,
+            group,
+            exclude_recent,
+            weight,
+            include_others,
         } => {
-            if let Some(avatar_id) = avatar_id {
-                switch_avatar(make_configuration_with_cookies(), &avatar_id).await;
-                return;
+            let target = match (avatar_id, query, alias) {
+                (Some(avatar_id), _, _) => Some(Target::Id(avatar_id)),
+                (_, Some(query), _) => Some(Target::Query(query)),
+                (_, _, Some(alias)) => Some(Target::Alias(alias)),
+                _ => None,
+            };
+            if let Some(target) = target {
+                match resolve_target(&target) {
+                    Ok(avatar_id) => {
+                        run_switch(output, &avatar_id, target.resolved_by(), target.input()).await;
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                }
             }
-            if let Some(query) = query {
-                match db::get_avatar_first_hit_by_name(&query) {
-                    Ok(avatar) => {
-                        if let Some(avatar) = avatar {
-                            println!("Found avatar: {} ({})", avatar.name, avatar.id);
-                            switch_avatar(make_configuration_with_cookies(), &avatar.id).await;
-                        } else {
-                            eprintln!("No avatar found matching query '{}'", query);
-                            std::process::exit(1);
-                        }
+
+            if let Some(steps) = back {
+                match db::get_previous_avatar_id(steps) {
+                    Ok(Some(avatar_id)) => {
+                        info!("Going back {} switch(es) to {}", steps, avatar_id);
+                        run_switch(output, &avatar_id, "back", &steps.to_string()).await;
+                        return;
+                    }
+                    Ok(None) => {
+                        eprintln!("Switch history does not go back {} avatar(s)", steps);
+                        std::process::exit(1);
                     }
                     Err(e) => {
-                        eprintln!("Error retrieving avatar for query '{}': {}", query, e);
+                        eprintln!("Error reading switch history: {}", e);
                         std::process::exit(1);
                     }
                 }
-                return;
             }
-            if let Some(alias) = alias {
-                match db::get_avatar_id_by_alias(&alias) {
-                    Ok(avatar_id) => {
-                        println!(
-                            "Resolved avatar alias in local database: {} ({})",
-                            &alias, avatar_id
-                        );
-                        switch_avatar(make_configuration_with_cookies(), &avatar_id).await;
+
+            if random {
+                let avatars = get_all_avatars().and_then(|avatars| {
+                    if include_others {
+                        return Ok(avatars);
+                    }
+                    let own = db::get_own_avatar_ids()?;
+                    Ok(avatars
+                        .into_iter()
+                        .filter(|avatar| own.contains(&avatar.id))
+                        .collect())
+                });
+                let candidates = match avatars {
+                    Ok(avatars) => {
+                        filter_by_tag_and_group(filter_by_platform(avatars, platform), tag, group)
+                    }
+                    Err(e) => {
+                        eprintln!("Error retrieving avatars from database: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+
+                match pick_random_avatar(candidates, exclude_recent, weight) {
+                    Ok(Some(avatar)) => {
+                        info!("Picked avatar: {} ({})", avatar.name, avatar.id);
+                        run_switch(output, &avatar.id, "random", &avatar.name).await;
                         return;
                     }
+                    Ok(None) => {
+                        eprintln!("No avatar matches the random filters");
+                        std::process::exit(1);
+                    }
                     Err(e) => {
-                        eprintln!("Error retrieving avatar ID for alias '{}': {}", alias, e);
+                        eprintln!("Error picking a random avatar: {}", e);
                         std::process::exit(1);
                     }
                 }
             }
 
-            eprintln!("Any of --id, --alias or --query must be provided for switching avatars.");
+            eprintln!(
+                "Any of --id, --alias, --query, --back or --random must be provided for switching avatars."
+            );
             std::process::exit(1);
         }
 
-        Commands::Search { query } => match db::get_avatars_by_name(&query) {
+        Commands::History { limit } => match db::get_history(limit) {
+            Ok(history) => print_records(output, &history),
+            Err(e) => {
+                eprintln!("Error retrieving switch history: {}", e);
+                std::process::exit(1);
+            }
+        },
+
+        Commands::Serve { port, token } => {
+            let token =
+                match serve::load_or_create_token(token.or(std::env::var("VAVC_SERVE_TOKEN").ok()))
+                {
+                    Ok(token) => token,
+                    Err(e) => {
+                        eprintln!("Error saving API token: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+
+            if let Err(e) = serve::serve(make_configuration_with_cookies(), port, token).await {
+                eprintln!("Error running HTTP server: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Stats { view } => {
+            let result = match view {
+                StatsView::Avatars => stats::avatar_stats().map(|s| print_records(output, &s)),
+                StatsView::NeverUsed => stats::never_used().map(|avatars| {
+                    let records: Vec<AvatarRecord> =
+                        avatars.iter().map(AvatarRecord::from).collect();
+                    print_records(output, &records)
+                }),
+                StatsView::Weekday => stats::weekday_stats().map(|s| print_records(output, &s)),
+            };
+
+            if let Err(e) = result {
+                eprintln!("Error computing statistics: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Search {
+            query,
+            platform,
+            tag,
+            group,
+            sort,
+        } => match db::get_avatars_by_name(&query) {
             Ok(avatars) => {
-                for avatar in &avatars {
-                    println!("{}: {}", avatar.name, avatar.id);
-                }
+                let avatars = filter_by_platform(avatars, platform);
+                let mut avatars = filter_by_tag_and_group(avatars, tag, group);
+                sort_avatars(&mut avatars, sort);
+                let records: Vec<AvatarRecord> = avatars.iter().map(AvatarRecord::from).collect();
+                print_records(output, &records);
 
-                println!();
-                println!("Total avatars found: {}", &avatars.len());
+                info!("Total avatars found: {}", &avatars.len());
             }
             Err(e) => {
                 eprintln!("Error retrieving avatars from database: {}", e);
@@ -264,14 +1973,20 @@ async fn main() {
             }
         },
 
-        Commands::List {} => {
+        Commands::List {
+            platform,
+            tag,
+            group,
+            sort,
+        } => {
             if let Ok(avatars) = get_all_avatars() {
-                for avatar in &avatars {
-                    println!("{}: {}", avatar.name, avatar.id);
-                }
+                let avatars = filter_by_platform(avatars, platform);
+                let mut avatars = filter_by_tag_and_group(avatars, tag, group);
+                sort_avatars(&mut avatars, sort);
+                let records: Vec<AvatarRecord> = avatars.iter().map(AvatarRecord::from).collect();
+                print_records(output, &records);
 
-                println!();
-                println!("Total avatars in database: {}", &avatars.len());
+                info!("Total avatars in database: {}", &avatars.len());
             } else {
                 eprintln!("Error retrieving avatars from database.");
                 std::process::exit(1);
@@ -280,9 +1995,225 @@ async fn main() {
     }
 }
 
+/// The pipeline URL to listen on, exiting if it cannot be listened to.
+fn checked_pipeline_url() -> url::Url {
+    match pipeline::pipeline_url() {
+        Ok(url) => url,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Switches through the agent when one is running, otherwise directly.
+async fn switch_once(avatar_id: &str, resolved_by: &str, input: &str) -> SwitchRecord {
+    #[cfg(unix)]
+    if let Some(record) = agent::switch_via_agent(avatar_id, resolved_by, input).await {
+        return record;
+    }
+
+    switch_and_record(
+        make_configuration_with_cookies(),
+        avatar_id,
+        resolved_by,
+        input,
+    )
+    .await
+}
+
+async fn run_switch(output: OutputFormat, avatar_id: &str, resolved_by: &str, input: &str) {
+    let record = switch_once(avatar_id, resolved_by, input).await;
+    print_record(output, &record);
+
+    if !record.success {
+        // A veto is the user's own rule, so it is not worked around with the fallback.
+        if !record.vetoed
+            && let Some(fallback) = &config::current().fallback_avatar
+        {
+            match resolve_target(&Target::alias_or_id(fallback)) {
+                Ok(fallback_id) if fallback_id != avatar_id => {
+                    warn!("Switching to fallback avatar {}", fallback);
+                    let record = switch_once(&fallback_id, "fallback", fallback).await;
+                    print_record(output, &record);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Cannot use the fallback avatar: {}", e),
+            }
+        }
+
+        std::process::exit(1);
+    }
+}
+
+fn sort_avatars(avatars: &mut [Avatar], sort: Option<SortOrder>) {
+    match sort {
+        None => {}
+        Some(SortOrder::Name) => avatars.sort_by(|a, b| a.name.cmp(&b.name)),
+        Some(SortOrder::Frecency) => {
+            if let Err(e) = stats::sort_by_frecency(avatars) {
+                eprintln!("Error reading switch history: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Collects the IDs of the selected avatars, in order and without repeats. Searches,
+/// tags and groups only pick our own uploads, since favourites cannot be edited.
+fn select_avatar_ids(selection: AvatarSelection) -> Vec<String> {
+    let mut ids = selection.id;
+    for alias in selection.alias {
+        match resolve_target(&Target::Alias(alias)) {
+            Ok(avatar_id) => ids.push(avatar_id),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if selection.query.is_some() || selection.tag.is_some() || selection.group.is_some() {
+        let avatars = match &selection.query {
+            Some(query) => db::get_avatars_by_name(query),
+            None => get_all_avatars(),
+        };
+        let own = db::get_own_avatar_ids();
+        let (avatars, own) = match (avatars, own) {
+            (Ok(avatars), Ok(own)) => (avatars, own),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("Error retrieving avatars from database: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let avatars = filter_by_tag_and_group(avatars, selection.tag, selection.group);
+        ids.extend(
+            avatars
+                .into_iter()
+                .map(|avatar| avatar.id)
+                .filter(|id| own.contains(id)),
+        );
+    }
+
+    let mut seen = HashSet::new();
+    ids.retain(|id| seen.insert(id.clone()));
+    ids
+}
+
+/// Flips the release status of the selected avatars and reports how each one went.
+async fn set_release_status(
+    output: OutputFormat,
+    selection: AvatarSelection,
+    release: avatar::Release,
+    dry_run: bool,
+    yes: bool,
+) {
+    let avatar_ids = select_avatar_ids(selection);
+    if avatar_ids.is_empty() {
+        eprintln!("No avatars selected");
+        std::process::exit(1);
+    }
+
+    let config = make_configuration_with_cookies();
+    let steps = avatar::plan_release(&config, &avatar_ids, release).await;
+    let planned = steps
+        .iter()
+        .filter(|step| step.record.result == avatar::ReleaseResult::Planned)
+        .count();
+
+    let records = if dry_run || planned == 0 {
+        steps.into_iter().map(|step| step.record).collect()
+    } else {
+        if !yes {
+            let answer = read_user_input(&format!(
+                "Change {} of {} avatar(s) to {}? [y/N] ",
+                planned,
+                avatar_ids.len(),
+                steps[0].record.to
+            ));
+            if !answer.eq_ignore_ascii_case("y") {
+                return;
+            }
+        }
+        avatar::apply_release(&config, steps).await
+    };
+    print_records(output, &records);
+
+    let count = |result| {
+        records
+            .iter()
+            .filter(|record| record.result == result)
+            .count()
+    };
+    let failed = count(avatar::ReleaseResult::Failed);
+    if dry_run {
+        info!(
+            "Dry run: {} would change, {} unchanged, {} failed",
+            planned,
+            count(avatar::ReleaseResult::Unchanged),
+            failed
+        );
+    } else {
+        info!(
+            "{} updated, {} unchanged, {} failed",
+            count(avatar::ReleaseResult::Updated),
+            count(avatar::ReleaseResult::Unchanged),
+            failed
+        );
+    }
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Prints the outcome of an alias command, exiting with an error if nothing could be done.
+fn print_alias_change(output: OutputFormat, record: AliasChangeRecord) {
+    let failed = matches!(record.result, AliasChange::Exists | AliasChange::NotFound);
+    print_record(output, &record);
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Applies `--tag` and `--group`, exiting if the database cannot be read.
+fn filter_by_tag_and_group(
+    avatars: Vec<Avatar>,
+    tag: Option<String>,
+    group: Option<String>,
+) -> Vec<Avatar> {
+    match filter::filter_by_tag_and_group(avatars, tag, group) {
+        Ok(avatars) => avatars,
+        Err(e) => {
+            eprintln!("Error retrieving tags/groups from database: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Resolves an avatar given as `--id`, `--query` or `--alias`, exiting if there is none.
+fn resolve_avatar_args(id: Option<String>, query: Option<String>, alias: Option<String>) -> String {
+    let target = match (id, query, alias) {
+        (Some(id), _, _) => Target::Id(id),
+        (_, Some(query), _) => Target::Query(query),
+        (_, _, Some(alias)) => Target::Alias(alias),
+        _ => unreachable!("clap requires one of --id, --query or --alias"),
+    };
+
+    match resolve_target(&target) {
+        Ok(avatar_id) => avatar_id,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prompts on stderr, so that questions never end up in the records printed on stdout.
 fn read_user_input(prompt: &str) -> String {
-    print!("{}", prompt);
-    io::stdout().flush().expect("Failed to flush stdout");
+    eprint!("{}", prompt);
+    io::stderr().flush().expect("Failed to flush stderr");
 
     let mut input = String::new();
     io::stdin()
@@ -291,3 +2222,36 @@ fn read_user_input(prompt: &str) -> String {
 
     input.trim().to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn global_flags_do_not_clash_with_subcommand_flags() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn global_flags_work_after_the_command() {
+        let cli = Cli::try_parse_from([
+            "vavc",
+            "list",
+            "-vv",
+            "--quiet",
+            "--log-file",
+            "--profile",
+            "alt",
+            "--output",
+            "tsv",
+        ])
+        .unwrap();
+        assert_eq!((cli.verbose, cli.quiet), (2, 1));
+        assert!(cli.log_file);
+        assert_eq!(cli.profile.as_deref(), Some("alt"));
+
+        let cli = Cli::try_parse_from(["vavc", "-q", "switch", "-q", "Foo"]).unwrap();
+        assert_eq!((cli.quiet, cli.quiet_short), (0, 1));
+    }
+}
@@ -0,0 +1,379 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unicode_width::UnicodeWidthStr;
+use vrchatapi::models::Avatar;
+
+use crate::db::{Alias, AliasChange, FavoriteGroupEntry, Preset, avatar_platforms};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Aligned columns for humans
+    #[default]
+    Table,
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Tab separated values with a header line
+    Tsv,
+}
+
+/// A row of command output. Its columns are the serialized field names, in order, and are
+/// spelled out so that an empty result still prints its header line.
+pub trait Record: Serialize {
+    const COLUMNS: &'static [&'static str];
+}
+
+impl<T: Record> Record for &T {
+    const COLUMNS: &'static [&'static str] = T::COLUMNS;
+}
+
+#[derive(Debug, Serialize)]
+pub struct AvatarRecord {
+    pub name: String,
+    pub id: String,
+    pub platforms: Vec<String>,
+}
+
+impl Record for AvatarRecord {
+    const COLUMNS: &'static [&'static str] = &["name", "id", "platforms"];
+}
+
+impl From<&Avatar> for AvatarRecord {
+    fn from(avatar: &Avatar) -> Self {
+        AvatarRecord {
+            name: avatar.name.clone(),
+            id: avatar.id.clone(),
+            platforms: avatar_platforms(avatar),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AliasRecord {
+    pub alias: String,
+    pub avatar_id: String,
+    pub avatar_name: Option<String>,
+    pub selector: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Record for AliasRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "alias",
+        "avatar_id",
+        "avatar_name",
+        "selector",
+        "description",
+    ];
+}
+
+impl From<Alias> for AliasRecord {
+    fn from(alias: Alias) -> Self {
+        AliasRecord {
+            alias: alias.name,
+            avatar_id: alias.avatar_id,
+            avatar_name: alias.avatar_name,
+            selector: alias.selector.map(|selector| selector.to_string()),
+            description: alias.description,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AliasChangeRecord {
+    pub alias: String,
+    pub result: AliasChange,
+    pub avatar_id: Option<String>,
+    pub previous_avatar_id: Option<String>,
+    pub warning: Option<String>,
+}
+
+impl Record for AliasChangeRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "alias",
+        "result",
+        "avatar_id",
+        "previous_avatar_id",
+        "warning",
+    ];
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagRecord {
+    pub tag: String,
+    pub avatar_id: String,
+    pub avatar_name: Option<String>,
+}
+
+impl Record for TagRecord {
+    const COLUMNS: &'static [&'static str] = &["tag", "avatar_id", "avatar_name"];
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupRecord {
+    pub group: String,
+    pub alias: String,
+    pub avatar_id: Option<String>,
+}
+
+impl Record for GroupRecord {
+    const COLUMNS: &'static [&'static str] = &["group", "alias", "avatar_id"];
+}
+
+#[derive(Debug, Serialize)]
+pub struct FavoriteRecord {
+    pub group: String,
+    pub avatar_id: String,
+    pub avatar_name: Option<String>,
+}
+
+impl Record for FavoriteRecord {
+    const COLUMNS: &'static [&'static str] = &["group", "avatar_id", "avatar_name"];
+}
+
+#[derive(Debug, Serialize)]
+pub struct FavoriteGroupRecord {
+    pub name: String,
+    pub display_name: String,
+    pub visibility: Option<String>,
+    pub avatars: usize,
+}
+
+impl Record for FavoriteGroupRecord {
+    const COLUMNS: &'static [&'static str] = &["name", "display_name", "visibility", "avatars"];
+}
+
+impl From<FavoriteGroupEntry> for FavoriteGroupRecord {
+    fn from(group: FavoriteGroupEntry) -> Self {
+        FavoriteGroupRecord {
+            name: group.name,
+            display_name: group.display_name,
+            visibility: group.visibility,
+            avatars: group.avatars,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresetRecord {
+    pub name: String,
+    pub avatar: Option<String>,
+    pub status: Option<String>,
+    pub status_description: Option<String>,
+    pub bio: Option<String>,
+}
+
+impl Record for PresetRecord {
+    const COLUMNS: &'static [&'static str] =
+        &["name", "avatar", "status", "status_description", "bio"];
+}
+
+impl From<Preset> for PresetRecord {
+    fn from(preset: Preset) -> Self {
+        PresetRecord {
+            name: preset.name,
+            avatar: preset.avatar,
+            status: preset.status,
+            status_description: preset.status_description,
+            bio: preset.bio,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CurrentRecord {
+    pub avatar_id: String,
+    pub avatar_name: Option<String>,
+    pub aliases: Vec<String>,
+    pub thumbnail_image_url: String,
+}
+
+impl Record for CurrentRecord {
+    const COLUMNS: &'static [&'static str] =
+        &["avatar_id", "avatar_name", "aliases", "thumbnail_image_url"];
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusRecord {
+    pub status: String,
+    pub status_description: String,
+}
+
+impl Record for StatusRecord {
+    const COLUMNS: &'static [&'static str] = &["status", "status_description"];
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthCheckRecord {
+    pub valid: bool,
+    pub error: Option<String>,
+}
+
+impl Record for AuthCheckRecord {
+    const COLUMNS: &'static [&'static str] = &["valid", "error"];
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SwitchRecord {
+    pub avatar_id: String,
+    pub resolved_by: String,
+    pub input: String,
+    pub success: bool,
+    /// Set when a pre-switch hook refused the switch, which is not a failure to retry.
+    #[serde(default)]
+    pub vetoed: bool,
+    pub error: Option<String>,
+}
+
+impl Record for SwitchRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "avatar_id",
+        "resolved_by",
+        "input",
+        "success",
+        "vetoed",
+        "error",
+    ];
+}
+
+pub fn print_records<T: Record>(format: OutputFormat, records: &[T]) {
+    let values: Vec<Value> = records
+        .iter()
+        .map(|record| serde_json::to_value(record).expect("Record is not serializable"))
+        .collect();
+    debug_assert!(
+        values.iter().all(columns_match::<T>),
+        "Record columns do not match its fields"
+    );
+
+    match format {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&values).expect("Record is not serializable")
+        ),
+        OutputFormat::Ndjson => {
+            for value in &values {
+                println!("{}", value);
+            }
+        }
+        OutputFormat::Tsv => {
+            println!("{}", T::COLUMNS.join("\t"));
+            for value in &values {
+                let cells: Vec<String> = T::COLUMNS
+                    .iter()
+                    .map(|h| cell_text(&value[h]).replace(['\t', '\n'], " "))
+                    .collect();
+                println!("{}", cells.join("\t"));
+            }
+        }
+        OutputFormat::Table => print_table(T::COLUMNS, &values),
+    }
+}
+
+pub fn print_record<T: Record>(format: OutputFormat, record: &T) {
+    print_records(format, std::slice::from_ref(record));
+}
+
+fn print_table(headers: &[&str], values: &[Value]) {
+    let rows: Vec<Vec<String>> = values
+        .iter()
+        .map(|value| {
+            headers
+                .iter()
+                .map(|h| cell_text(&value[h]).replace('\n', " "))
+                .collect()
+        })
+        .collect();
+
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.width()).collect();
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.width());
+        }
+    }
+
+    let line = |cells: &[String]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| format!("{}{}", cell, " ".repeat(widths[i] - cell.width())))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+
+    line(&headers.iter().map(|h| h.to_uppercase()).collect::<Vec<_>>());
+    for row in &rows {
+        line(row);
+    }
+}
+
+fn columns_match<T: Record>(value: &Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|map| map.keys().eq(T::COLUMNS.iter()))
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(cell_text).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_columns<T: Record>(record: T) {
+        let value = serde_json::to_value(&record).unwrap();
+        assert!(
+            columns_match::<T>(&value),
+            "{:?} vs {:?}",
+            value,
+            T::COLUMNS
+        );
+    }
+
+    #[test]
+    fn columns_match_the_serialized_fields() {
+        assert_columns(SwitchRecord {
+            avatar_id: String::from("avtr_1"),
+            resolved_by: String::from("id"),
+            input: String::from("avtr_1"),
+            success: false,
+            vetoed: true,
+            error: Some(String::from("no")),
+        });
+        assert_columns(AliasChangeRecord {
+            alias: String::from("a"),
+            result: AliasChange::Created,
+            avatar_id: None,
+            previous_avatar_id: None,
+            warning: None,
+        });
+        assert_columns(CurrentRecord {
+            avatar_id: String::from("avtr_1"),
+            avatar_name: None,
+            aliases: Vec::new(),
+            thumbnail_image_url: String::new(),
+        });
+        assert_columns(StatusRecord {
+            status: String::from("active"),
+            status_description: String::new(),
+        });
+        assert_columns(AuthCheckRecord {
+            valid: true,
+            error: None,
+        });
+        assert_columns(GroupRecord {
+            group: String::from("g"),
+            alias: String::from("a"),
+            avatar_id: None,
+        });
+    }
+}
@@ -0,0 +1,292 @@
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderValue, header};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async_with_config};
+use tracing::{debug, info, warn};
+use vrchatapi::apis::configuration::Configuration;
+
+use crate::avatar::lookup_avatar;
+use crate::config;
+use crate::db;
+use crate::secret::read_auth_token;
+use crate::switch::get_current_avatar_id;
+
+/// How long to wait before deciding whether a change was made by vavc, which records
+/// its switches only once the API call returns.
+const SETTLE: Duration = Duration::from_secs(3);
+
+const RECONNECT_MIN: Duration = Duration::from_secs(5);
+const RECONNECT_MAX: Duration = Duration::from_secs(300);
+
+/// Refuse messages larger than this rather than allocating whatever the server claims.
+const MAX_MESSAGE: usize = 16 * 1024 * 1024;
+
+type Socket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn connect(url: &url::Url) -> Result<Socket, tungstenite::Error> {
+    let mut request = url.as_str().into_client_request()?;
+    if let Ok(user_agent) = HeaderValue::from_str(&config::current().user_agent) {
+        request.headers_mut().insert(header::USER_AGENT, user_agent);
+    }
+    let config = WebSocketConfig::default().max_message_size(Some(MAX_MESSAGE));
+
+    let (socket, _) = connect_async_with_config(request, Some(config), false).await?;
+    Ok(socket)
+}
+
+#[derive(Debug, Deserialize)]
+struct PipelineMessage {
+    r#type: String,
+    /// Usually a JSON document encoded as a string.
+    content: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserUpdate {
+    user: UpdatedUser,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdatedUser {
+    #[serde(default)]
+    current_avatar: Option<String>,
+    #[serde(default)]
+    current_avatar_image_url: Option<String>,
+}
+
+/// What the listener last saw, kept across reconnects.
+#[derive(Default)]
+struct Seen {
+    avatar_id: Option<String>,
+    image_url: Option<String>,
+}
+
+/// Follows the pipeline at `url` and sends the ID of every avatar we change to in-game,
+/// after recording it to history. Changes made through vavc are already in history and
+/// are not sent. Reconnects until the receiving side goes away.
+pub async fn listen(config: Configuration, url: url::Url, changes: mpsc::UnboundedSender<String>) {
+    follow(config, url, changes, SETTLE).await
+}
+
+async fn follow(
+    config: Configuration,
+    url: url::Url,
+    changes: mpsc::UnboundedSender<String>,
+    settle: Duration,
+) {
+    let mut seen = Seen {
+        avatar_id: get_current_avatar_id(&config).await.ok(),
+        image_url: None,
+    };
+    let mut delay = RECONNECT_MIN;
+
+    while !changes.is_closed() {
+        match connect(&url).await {
+            Ok(mut socket) => {
+                info!("Connected to the pipeline");
+                delay = RECONNECT_MIN;
+                loop {
+                    match socket.next().await {
+                        Some(Ok(Message::Text(text))) => {
+                            handle_message(&config, &text, &mut seen, &changes, settle).await
+                        }
+                        Some(Ok(Message::Close(_))) | None => {
+                            info!("Pipeline closed the connection");
+                            break;
+                        }
+                        // Pings are answered by the socket itself.
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => {
+                            warn!("Pipeline connection lost: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => warn!("Could not connect to the pipeline: {}", e),
+        }
+
+        debug!("Reconnecting to the pipeline in {:?}", delay);
+        sleep(delay).await;
+        delay = (delay * 2).min(RECONNECT_MAX);
+    }
+}
+
+/// The configured pipeline URL with our auth token, which `listen` connects to.
+/// Fails if there is no token to listen with.
+pub fn pipeline_url() -> Result<url::Url, String> {
+    let mut url = url::Url::parse(&config::current().pipeline_url)
+        .map_err(|e| format!("Invalid pipeline_url: {}", e))?;
+    let token = read_auth_token()
+        .ok_or_else(|| String::from("No auth cookie found. Please authenticate first."))?;
+    url.query_pairs_mut().append_pair("authToken", &token);
+    Ok(url)
+}
+
+async fn handle_message(
+    config: &Configuration,
+    text: &str,
+    seen: &mut Seen,
+    changes: &mpsc::UnboundedSender<String>,
+    settle: Duration,
+) {
+    let message: PipelineMessage = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(e) => {
+            debug!("Ignoring unreadable pipeline message: {}", e);
+            return;
+        }
+    };
+    if message.r#type != "user-update" {
+        return;
+    }
+    let content = match message.content {
+        Value::String(content) => serde_json::from_str(&content),
+        content => serde_json::from_value(content),
+    };
+    let user = match content {
+        Ok(UserUpdate { user }) => user,
+        Err(e) => {
+            debug!("Ignoring unreadable user-update: {}", e);
+            return;
+        }
+    };
+
+    // Not every update carries the avatar ID; a new avatar image means asking for it.
+    let avatar_id = match user.current_avatar.filter(|id| !id.is_empty()) {
+        Some(avatar_id) => avatar_id,
+        None if user.current_avatar_image_url.is_some()
+            && user.current_avatar_image_url != seen.image_url =>
+        {
+            seen.image_url = user.current_avatar_image_url;
+            match get_current_avatar_id(config).await {
+                Ok(avatar_id) => avatar_id,
+                Err(e) => {
+                    warn!("{}", e);
+                    return;
+                }
+            }
+        }
+        None => return,
+    };
+    if seen.avatar_id.as_ref() == Some(&avatar_id) {
+        return;
+    }
+    seen.avatar_id = Some(avatar_id.clone());
+
+    sleep(settle).await;
+    match db::get_previous_avatar_id(0) {
+        Ok(Some(last)) if last == avatar_id => {
+            debug!("Avatar change to {} was made by vavc", avatar_id);
+            return;
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to read switch history: {}", e),
+    }
+
+    if let Err(e) = lookup_avatar(config, &avatar_id).await {
+        debug!("Could not look up {}: {}", avatar_id, e);
+    }
+    if let Err(e) = db::record_switch(&avatar_id, "in-game", "pipeline", None) {
+        warn!("Failed to record switch history: {}", e);
+    }
+    info!("Avatar changed in-game to {}", avatar_id);
+    let _ = changes.send(avatar_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio::time::timeout;
+
+    use crate::test_support;
+
+    /// Serves one WebSocket client and sends it every text it is handed.
+    async fn pipeline_server() -> (url::Url, mpsc::UnboundedSender<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = url::Url::parse(&format!("ws://{}/", listener.local_addr().unwrap())).unwrap();
+        let (frames, mut to_send) = mpsc::unbounded_channel::<String>();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(text) = to_send.recv().await {
+                socket.send(Message::text(text)).await.unwrap();
+            }
+        });
+
+        (url, frames)
+    }
+
+    fn user_update(avatar_id: &str, as_string: bool) -> String {
+        let content = serde_json::json!({
+            "userId": "usr_test",
+            "user": { "currentAvatar": avatar_id },
+        });
+        let content = if as_string {
+            Value::String(content.to_string())
+        } else {
+            content
+        };
+        serde_json::json!({ "type": "user-update", "content": content }).to_string()
+    }
+
+    async fn next_change(changes: &mut mpsc::UnboundedReceiver<String>) -> String {
+        timeout(Duration::from_secs(30), changes.recv())
+            .await
+            .expect("No change was sent")
+            .expect("Listener stopped")
+    }
+
+    #[tokio::test]
+    async fn records_in_game_changes_and_skips_our_own() {
+        test_support::setup();
+        let (url, frames) = pipeline_server().await;
+        // Nothing listens here, so API lookups fail quickly.
+        let config = Configuration {
+            base_path: String::from("http://127.0.0.1:1/api/1"),
+            ..Default::default()
+        };
+        let (tx, mut changes) = mpsc::unbounded_channel();
+        tokio::spawn(follow(config, url, tx, Duration::ZERO));
+
+        frames.send(user_update("avtr_pipeline_1", true)).unwrap();
+        assert_eq!(next_change(&mut changes).await, "avtr_pipeline_1");
+
+        frames.send(user_update("avtr_pipeline_2", false)).unwrap();
+        assert_eq!(next_change(&mut changes).await, "avtr_pipeline_2");
+
+        // A switch vavc made itself is already in history and must not come back.
+        db::record_switch("avtr_pipeline_3", "id", "avtr_pipeline_3", None).unwrap();
+        frames.send(user_update("avtr_pipeline_3", true)).unwrap();
+        frames.send(user_update("avtr_pipeline_4", true)).unwrap();
+        assert_eq!(next_change(&mut changes).await, "avtr_pipeline_4");
+
+        let history: Vec<(String, String)> = db::get_history(10)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.avatar_id.starts_with("avtr_pipeline_"))
+            .map(|entry| (entry.avatar_id, entry.resolved_by))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                ("avtr_pipeline_4", "in-game"),
+                ("avtr_pipeline_3", "id"),
+                ("avtr_pipeline_2", "in-game"),
+                ("avtr_pipeline_1", "in-game"),
+            ]
+            .map(|(id, by)| (id.to_string(), by.to_string()))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use tracing::{info, warn};
+use vrchatapi::apis::configuration::Configuration;
+use vrchatapi::models::UserStatus;
+
+use crate::db::Preset;
+use crate::hooks;
+use crate::switch::{Target, get_current_user, resolve_target, switch_and_record_approved};
+use crate::user::{Status, update_profile};
+
+/// Puts on a preset: the profile first, then the avatar. The pre-switch hooks run before
+/// either, so a veto leaves everything as it was. If the avatar switch fails, the profile
+/// fields the preset touched are put back the way they were.
+pub async fn apply_preset(config: &Configuration, preset: &Preset) -> Result<(), String> {
+    // Resolve everything up front so a typo fails before anything changes.
+    let avatar_id = preset
+        .avatar
+        .as_deref()
+        .map(|avatar| resolve_target(&Target::alias_or_id(avatar)))
+        .transpose()?;
+    let status = preset
+        .status
+        .as_deref()
+        .map(Status::parse)
+        .transpose()?
+        .map(UserStatus::from);
+
+    let before = get_current_user(config).await?;
+    let changes_profile =
+        status.is_some() || preset.status_description.is_some() || preset.bio.is_some();
+    let avatar_id = avatar_id.filter(|avatar_id| *avatar_id != before.current_avatar);
+    let input = format!("preset: {}", preset.name);
+
+    if let Some(avatar_id) = &avatar_id {
+        hooks::pre_switch(avatar_id, "preset", &input)
+            .await
+            .map_err(|reason| {
+                format!("Vetoed by pre-switch hook: {}; nothing was changed", reason)
+            })?;
+    }
+
+    if changes_profile {
+        update_profile(
+            config,
+            &before.id,
+            status,
+            preset.status_description.clone(),
+            preset.bio.clone(),
+        )
+        .await?;
+    }
+
+    if let Some(avatar_id) = avatar_id {
+        let record = switch_and_record_approved(config.clone(), &avatar_id, "preset", &input).await;
+        if !record.success {
+            let error = record.error.unwrap_or_default();
+            if !changes_profile {
+                return Err(format!("Failed to switch avatar: {}", error));
+            }
+
+            warn!("Failed to switch avatar, restoring the previous profile");
+            // Offline is only ever reported by the API, so it cannot be put back.
+            let before_status = status.map(|_| before.status);
+            if before_status == Some(UserStatus::Offline) {
+                warn!(
+                    "The previous status was offline, which cannot be set; leaving the status as it is"
+                );
+            }
+            let before_status = before_status.filter(|status| *status != UserStatus::Offline);
+            let restored = update_profile(
+                config,
+                &before.id,
+                before_status,
+                preset
+                    .status_description
+                    .as_ref()
+                    .map(|_| before.status_description.clone()),
+                preset.bio.as_ref().map(|_| before.bio.clone()),
+            )
+            .await;
+            return Err(match restored {
+                Ok(_) => format!(
+                    "Failed to switch avatar: {}; the profile was restored",
+                    error
+                ),
+                Err(e) => format!(
+                    "Failed to switch avatar: {}; restoring the profile also failed: {}",
+                    error, e
+                ),
+            });
+        }
+    }
+
+    info!("Applied preset '{}'", preset.name);
+    Ok(())
+}
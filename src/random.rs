@@ -0,0 +1,65 @@
+use clap::ValueEnum;
+use rand::distr::weighted::WeightedIndex;
+use rand::prelude::*;
+use vrchatapi::models::Avatar;
+
+use crate::{db, stats};
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum RandomWeight {
+    /// Every candidate is equally likely
+    #[default]
+    None,
+    /// Frequently and recently worn avatars are more likely
+    Usage,
+    /// Avatars in our favourite groups are more likely
+    Favorites,
+}
+
+/// Picks one of `candidates`, skipping the `exclude_recent` most recently worn avatars.
+pub fn pick_random_avatar(
+    candidates: Vec<Avatar>,
+    exclude_recent: usize,
+    weight: RandomWeight,
+) -> Result<Option<Avatar>, rusqlite::Error> {
+    let recent = db::get_recent_avatar_ids(exclude_recent)?;
+    let candidates: Vec<Avatar> = candidates
+        .into_iter()
+        .filter(|avatar| !recent.contains(&avatar.id))
+        .collect();
+
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+
+    let weights: Vec<f64> = match weight {
+        RandomWeight::None => vec![1.0; candidates.len()],
+        RandomWeight::Usage => {
+            let scores = stats::frecency_scores()?;
+            candidates
+                .iter()
+                // Unworn avatars keep a small chance of being picked.
+                .map(|avatar| 10.0 + scores.get(&avatar.id).copied().unwrap_or(0.0))
+                .collect()
+        }
+        RandomWeight::Favorites => {
+            let favorites = db::get_favorited_avatar_ids()?;
+            candidates
+                .iter()
+                .map(|avatar| {
+                    if favorites.contains(&avatar.id) {
+                        5.0
+                    } else {
+                        1.0
+                    }
+                })
+                .collect()
+        }
+    };
+
+    let index = WeightedIndex::new(&weights)
+        .expect("Weights are positive")
+        .sample(&mut rand::rng());
+
+    Ok(candidates.into_iter().nth(index))
+}
@@ -0,0 +1,40 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{Instant, sleep_until};
+use tracing::debug;
+
+const API_MIN_INTERVAL: Duration = Duration::from_secs(2);
+
+static API: OnceLock<RateLimiter> = OnceLock::new();
+
+/// Spaces out calls so that at most one goes through per `min_interval`.
+pub struct RateLimiter {
+    min_interval: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(min_interval: Duration) -> Self {
+        RateLimiter {
+            min_interval,
+            next: Mutex::new(None),
+        }
+    }
+
+    pub async fn wait(&self) {
+        let mut next = self.next.lock().await;
+        if let Some(at) = *next {
+            if at > Instant::now() {
+                debug!("Waiting {:?} for rate limiter", at - Instant::now());
+            }
+            sleep_until(at).await;
+        }
+        *next = Some(Instant::now() + self.min_interval);
+    }
+}
+
+/// Limiter shared by everything in this process that calls the VRChat API.
+pub fn api() -> &'static RateLimiter {
+    API.get_or_init(|| RateLimiter::new(API_MIN_INTERVAL))
+}
@@ -1,19 +1,25 @@
 use reqwest::cookie::{self};
 use std::sync::Arc;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::config;
+use crate::log::redact_cookies;
 
 pub fn read_secret_in_directory() -> Option<Vec<String>> {
-    println!("Reading cookies from ./secret");
-    match std::fs::read_to_string("./secret") {
+    let path = config::current().secret_path();
+    debug!("Reading cookies from {}", path);
+    match std::fs::read_to_string(&path) {
         Ok(content) => {
             let lines: Vec<String> = content
                 .lines()
                 .map(|line| line.trim().to_string())
                 .collect();
             if lines.len() >= 2 {
-                println!("Cookies read successfully from ./secret");
+                debug!("Cookies read successfully from {}", path);
+                trace!("Cookies: {}", redact_cookies(&lines.join("; ")));
                 Some(lines)
             } else {
-                eprintln!("Invalid cookie format in ./secret");
+                warn!("Invalid cookie format in {}", path);
                 None
             }
         }
@@ -21,13 +27,27 @@ pub fn read_secret_in_directory() -> Option<Vec<String>> {
     }
 }
 
+/// The value of the `auth` cookie, which the pipeline websocket takes as its token.
+pub fn read_auth_token() -> Option<String> {
+    read_secret_in_directory()?
+        .iter()
+        .find_map(|cookie| cookie.strip_prefix("auth="))
+        .map(|token| token.to_string())
+}
+
 pub fn write_secret_in_directory<C>(cookie_store: Arc<C>)
 where
     C: cookie::CookieStore + 'static,
 {
-    let cookies = cookie_store
-        .cookies(&url::Url::parse("https://api.vrchat.cloud").expect("Invalid URL"))
-        .unwrap();
+    let path = config::current().secret_path();
+    let origin = match config::current().api_origin() {
+        Ok(origin) => origin,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let cookies = cookie_store.cookies(&origin).unwrap();
 
     let auth_cookie = cookies
         .to_str()
@@ -45,12 +65,9 @@ where
         .map(|cookie| cookie.trim().to_string())
         .unwrap_or_default();
 
-    if let Err(e) = std::fs::write(
-        "./secret",
-        format!("{}\n{}", auth_cookie, two_factor_cookie),
-    ) {
-        eprintln!("Failed to save cookies: {}", e);
+    if let Err(e) = std::fs::write(&path, format!("{}\n{}", auth_cookie, two_factor_cookie)) {
+        error!("Failed to save cookies: {}", e);
     } else {
-        println!("Cookies saved successfully to ./secret");
+        info!("Cookies saved successfully to {}", path);
     }
 }
@@ -0,0 +1,264 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use rand::distr::{Alphanumeric, SampleString};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tracing::{info, warn};
+use vrchatapi::apis::configuration::Configuration;
+
+use crate::db;
+use crate::filter::filter_by_platform;
+use crate::output::{AliasRecord, AvatarRecord};
+use crate::switch::{Target, get_current_avatar_id, resolve_target, switch_and_record};
+
+const TOKEN_PATH: &str = "./serve-token";
+
+struct AppState {
+    config: Configuration,
+    token: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ErrorBody { error: self.1 })).into_response()
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(e: rusqlite::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+/// Runs database work on the blocking thread pool, off the async workers.
+async fn blocking<T, F>(work: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, ApiError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+}
+
+#[derive(Debug, Deserialize)]
+struct ListParams {
+    q: Option<String>,
+    platform: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryParams {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SwitchBody {
+    id: Option<String>,
+    alias: Option<String>,
+    query: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CurrentBody {
+    avatar_id: String,
+    name: Option<String>,
+}
+
+/// Uses the given token, or the one saved in `./serve-token`, creating it on first use.
+pub fn load_or_create_token(token: Option<String>) -> Result<String, std::io::Error> {
+    if let Some(token) = token {
+        return Ok(token);
+    }
+
+    match std::fs::read_to_string(TOKEN_PATH) {
+        Ok(token) if !token.trim().is_empty() => Ok(token.trim().to_string()),
+        _ => {
+            let token = Alphanumeric.sample_string(&mut rand::rng(), 32);
+            write_private(TOKEN_PATH, &token)?;
+            info!("Generated a new API token in {}", TOKEN_PATH);
+            Ok(token)
+        }
+    }
+}
+
+/// Writes a file only the current user can read.
+fn write_private(path: &str, content: &str) -> Result<(), std::io::Error> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files; an existing empty one keeps its own.
+        if std::fs::metadata(path).is_ok() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    options.open(path)?.write_all(content.as_bytes())
+}
+
+pub async fn serve(config: Configuration, port: u16, token: String) -> Result<(), std::io::Error> {
+    let state = Arc::new(AppState { config, token });
+
+    let app = Router::new()
+        .route("/avatars", get(list_avatars))
+        .route("/aliases", get(list_aliases))
+        .route("/aliases/{name}", get(get_alias))
+        .route("/switch", post(switch))
+        .route("/current", get(current))
+        .route("/history", get(history))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
+    info!("Listening on http://{}", listener.local_addr()?);
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+}
+
+async fn require_token(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| bool::from(token.as_bytes().ct_eq(state.token.as_bytes())));
+
+    if !authorized {
+        warn!("Rejected unauthorized request to {}", request.uri());
+        return Err(ApiError(
+            StatusCode::UNAUTHORIZED,
+            String::from("Missing or invalid bearer token"),
+        ));
+    }
+
+    Ok(next.run(request).await)
+}
+
+async fn list_avatars(
+    Query(params): Query<ListParams>,
+) -> Result<Json<Vec<AvatarRecord>>, ApiError> {
+    let avatars = blocking(move || {
+        Ok(match &params.q {
+            Some(q) => db::get_avatars_by_name(q)?,
+            None => db::get_all_avatars()?,
+        })
+    })
+    .await?;
+    let avatars = filter_by_platform(avatars, params.platform);
+
+    Ok(Json(avatars.iter().map(AvatarRecord::from).collect()))
+}
+
+async fn list_aliases() -> Result<Json<Vec<AliasRecord>>, ApiError> {
+    let aliases = blocking(|| {
+        db::create_alias_db()?;
+        Ok(db::get_all_aliases()?)
+    })
+    .await?;
+
+    Ok(Json(aliases.into_iter().map(AliasRecord::from).collect()))
+}
+
+async fn get_alias(Path(name): Path<String>) -> Result<Json<AliasRecord>, ApiError> {
+    blocking(move || {
+        db::create_alias_db()?;
+
+        match db::get_alias(&name)? {
+            Some(alias) => Ok(Json(AliasRecord::from(alias))),
+            None => Err(ApiError(
+                StatusCode::NOT_FOUND,
+                format!("No alias named '{}'", name),
+            )),
+        }
+    })
+    .await
+}
+
+async fn switch(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<SwitchBody>,
+) -> Result<Response, ApiError> {
+    let target = match (body.id, body.alias, body.query) {
+        (Some(id), None, None) => Target::Id(id),
+        (None, Some(alias), None) => Target::Alias(alias),
+        (None, None, Some(query)) => Target::Query(query),
+        _ => {
+            return Err(ApiError(
+                StatusCode::BAD_REQUEST,
+                String::from("Exactly one of id, alias or query is required"),
+            ));
+        }
+    };
+
+    let resolving = target.clone();
+    let avatar_id = blocking(move || {
+        resolve_target(&resolving).map_err(|e| ApiError(StatusCode::NOT_FOUND, e))
+    })
+    .await?;
+    // Hooks, the avatar lookup and the history all touch the database, so the whole switch
+    // runs on the blocking pool and drives its API calls from there.
+    let config = state.config.clone();
+    let runtime = tokio::runtime::Handle::current();
+    let record = blocking(move || {
+        Ok(runtime.block_on(switch_and_record(
+            config,
+            &avatar_id,
+            target.resolved_by(),
+            target.input(),
+        )))
+    })
+    .await?;
+
+    let status = if record.success {
+        StatusCode::OK
+    } else if record.vetoed {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::BAD_GATEWAY
+    };
+    Ok((status, Json(record)).into_response())
+}
+
+async fn current(State(state): State<Arc<AppState>>) -> Result<Json<CurrentBody>, ApiError> {
+    let avatar_id = get_current_avatar_id(&state.config)
+        .await
+        .map_err(|e| ApiError(StatusCode::BAD_GATEWAY, e))?;
+    let id = avatar_id.clone();
+    let name = blocking(move || Ok(db::get_avatar_by_id(&id)?.map(|avatar| avatar.name))).await?;
+
+    Ok(Json(CurrentBody { avatar_id, name }))
+}
+
+async fn history(
+    Query(params): Query<HistoryParams>,
+) -> Result<Json<Vec<db::HistoryEntry>>, ApiError> {
+    let history = blocking(move || Ok(db::get_history(params.limit.unwrap_or(20))?)).await?;
+
+    Ok(Json(history))
+}
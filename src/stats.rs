@@ -0,0 +1,176 @@
+use chrono::{DateTime, Datelike, Local, Utc, Weekday};
+use serde::Serialize;
+use std::collections::HashMap;
+use vrchatapi::models::Avatar;
+
+use crate::db;
+use crate::output::Record;
+
+#[derive(Debug, Serialize)]
+pub struct AvatarStats {
+    pub avatar_id: String,
+    pub name: Option<String>,
+    pub switches: usize,
+    pub last_used: String,
+    pub worn: String,
+    pub worn_secs: i64,
+}
+
+impl Record for AvatarStats {
+    const COLUMNS: &'static [&'static str] = &[
+        "avatar_id",
+        "name",
+        "switches",
+        "last_used",
+        "worn",
+        "worn_secs",
+    ];
+}
+
+#[derive(Debug, Serialize)]
+pub struct WeekdayStats {
+    pub weekday: String,
+    pub switches: usize,
+}
+
+impl Record for WeekdayStats {
+    const COLUMNS: &'static [&'static str] = &["weekday", "switches"];
+}
+
+struct Switch {
+    at: DateTime<Utc>,
+    avatar_id: String,
+}
+
+fn load_switches() -> Result<Vec<Switch>, rusqlite::Error> {
+    Ok(db::get_successful_switches()?
+        .into_iter()
+        .filter_map(|(at, avatar_id)| {
+            DateTime::parse_from_rfc3339(&at).ok().map(|at| Switch {
+                at: at.with_timezone(&Utc),
+                avatar_id,
+            })
+        })
+        .collect())
+}
+
+/// Per-avatar usage, most switched-to first. An avatar counts as worn until the next switch.
+pub fn avatar_stats() -> Result<Vec<AvatarStats>, rusqlite::Error> {
+    db::create_avatar_db()?;
+    let switches = load_switches()?;
+    let names: HashMap<String, String> = db::get_all_avatars()?
+        .into_iter()
+        .map(|avatar| (avatar.id, avatar.name))
+        .collect();
+
+    let now = Utc::now();
+    let mut stats: HashMap<String, AvatarStats> = HashMap::new();
+    for (i, switch) in switches.iter().enumerate() {
+        let until = switches.get(i + 1).map(|next| next.at).unwrap_or(now);
+        let entry = stats
+            .entry(switch.avatar_id.clone())
+            .or_insert_with(|| AvatarStats {
+                avatar_id: switch.avatar_id.clone(),
+                name: names.get(&switch.avatar_id).cloned(),
+                switches: 0,
+                last_used: String::new(),
+                worn: String::new(),
+                worn_secs: 0,
+            });
+        entry.switches += 1;
+        entry.last_used = switch
+            .at
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string();
+        entry.worn_secs += (until - switch.at).num_seconds().max(0);
+    }
+
+    let mut stats: Vec<AvatarStats> = stats.into_values().collect();
+    for entry in &mut stats {
+        entry.worn = format_duration(entry.worn_secs);
+    }
+    stats.sort_by(|a, b| {
+        b.switches
+            .cmp(&a.switches)
+            .then(b.last_used.cmp(&a.last_used))
+    });
+
+    Ok(stats)
+}
+
+/// Our own avatars that have never been switched to.
+pub fn never_used() -> Result<Vec<Avatar>, rusqlite::Error> {
+    db::create_avatar_db()?;
+    let used: Vec<String> = load_switches()?.into_iter().map(|s| s.avatar_id).collect();
+    let own = db::get_own_avatar_ids()?;
+
+    Ok(db::get_all_avatars()?
+        .into_iter()
+        .filter(|avatar| own.contains(&avatar.id) && !used.contains(&avatar.id))
+        .collect())
+}
+
+/// Switch counts per local weekday, Monday first.
+pub fn weekday_stats() -> Result<Vec<WeekdayStats>, rusqlite::Error> {
+    let mut counts: HashMap<Weekday, usize> = HashMap::new();
+    for switch in load_switches()? {
+        *counts
+            .entry(switch.at.with_timezone(&Local).weekday())
+            .or_default() += 1;
+    }
+
+    let mut day = Weekday::Mon;
+    let mut stats = Vec::new();
+    for _ in 0..7 {
+        stats.push(WeekdayStats {
+            weekday: day.to_string(),
+            switches: counts.get(&day).copied().unwrap_or(0),
+        });
+        day = day.succ();
+    }
+
+    Ok(stats)
+}
+
+/// Frequency weighted by recency: recent switches count for more than old ones.
+pub fn frecency_scores() -> Result<HashMap<String, f64>, rusqlite::Error> {
+    let now = Utc::now();
+    let mut scores: HashMap<String, f64> = HashMap::new();
+
+    for switch in load_switches()? {
+        let weight = match (now - switch.at).num_days() {
+            ..=4 => 100.0,
+            5..=14 => 70.0,
+            15..=31 => 50.0,
+            32..=90 => 30.0,
+            _ => 10.0,
+        };
+        *scores.entry(switch.avatar_id).or_default() += weight;
+    }
+
+    Ok(scores)
+}
+
+/// Sorts avatars by frecency, highest first; ties keep their current order.
+pub fn sort_by_frecency(avatars: &mut [Avatar]) -> Result<(), rusqlite::Error> {
+    let scores = frecency_scores()?;
+    avatars.sort_by(|a, b| {
+        let a = scores.get(&a.id).copied().unwrap_or(0.0);
+        let b = scores.get(&b.id).copied().unwrap_or(0.0);
+        b.total_cmp(&a)
+    });
+
+    Ok(())
+}
+
+fn format_duration(secs: i64) -> String {
+    let (days, hours, minutes) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
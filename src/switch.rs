@@ -1,12 +1,295 @@
-use vrchatapi::apis::{self, configuration::Configuration};
+use tracing::{error, info, warn};
+use vrchatapi::{
+    apis::{self, avatars_api::SelectAvatarError, configuration::Configuration},
+    models::{Avatar, CurrentUser, EitherUserOrTwoFactor},
+};
 
-pub async fn switch_avatar(configuration: Configuration, avatar_id: &str) {
-    println!("Switching to avatar ID: {}", avatar_id);
+use crate::avatar::lookup_avatar;
+use crate::db::{Alias, Selector};
+use crate::log::{trace_request, trace_response};
+use crate::output::SwitchRecord;
+use crate::{db, hooks, ratelimit};
 
+/// How an avatar to switch to was named.
+#[derive(Debug, Clone)]
+pub enum Target {
+    Id(String),
+    Query(String),
+    Alias(String),
+}
+
+impl Target {
+    /// Takes an avatar ID as is and anything else as an alias, so a mistyped alias fails
+    /// to resolve instead of being sent to the API as an ID.
+    pub fn alias_or_id(name: &str) -> Target {
+        if name.starts_with("avtr_") {
+            Target::Id(name.to_string())
+        } else {
+            Target::Alias(name.to_string())
+        }
+    }
+
+    pub fn resolved_by(&self) -> &str {
+        match self {
+            Target::Id(_) => "id",
+            Target::Query(_) => "query",
+            Target::Alias(_) => "alias",
+        }
+    }
+
+    pub fn input(&self) -> &str {
+        match self {
+            Target::Id(s) | Target::Query(s) | Target::Alias(s) => s,
+        }
+    }
+}
+
+/// Looks a target up in the local database and returns the avatar ID to switch to.
+pub fn resolve_target(target: &Target) -> Result<String, String> {
+    match target {
+        Target::Id(avatar_id) => Ok(avatar_id.clone()),
+        Target::Query(query) => match db::get_avatar_first_hit_by_name(query) {
+            Ok(Some(avatar)) => {
+                info!("Found avatar: {} ({})", avatar.name, avatar.id);
+                Ok(avatar.id)
+            }
+            Ok(None) => Err(format!("No avatar found matching query '{}'", query)),
+            Err(e) => Err(format!(
+                "Error retrieving avatar for query '{}': {}",
+                query, e
+            )),
+        },
+        Target::Alias(alias) => match db::get_alias(alias) {
+            Ok(Some(Alias {
+                avatar_id,
+                selector: Some(selector),
+                ..
+            })) => {
+                let avatar =
+                    resolve_selector(&selector).map_err(|e| format!("Alias '{}': {}", alias, e))?;
+                if avatar.id != avatar_id {
+                    info!(
+                        "Alias '{}' now selects {} (was {})",
+                        alias, avatar.id, avatar_id
+                    );
+                    if let Err(e) = db::set_alias_match(alias, &avatar.id, &avatar.name) {
+                        warn!("Failed to update alias '{}': {}", alias, e);
+                    }
+                }
+                info!(
+                    "Resolved avatar alias by {}: {} ({})",
+                    selector, alias, avatar.id
+                );
+                Ok(avatar.id)
+            }
+            Ok(Some(found)) => {
+                info!(
+                    "Resolved avatar alias in local database: {} ({})",
+                    alias, found.avatar_id
+                );
+                Ok(found.avatar_id)
+            }
+            Ok(None) => Err(format!("No alias named '{}'", alias)),
+            Err(e) => Err(format!(
+                "Error retrieving avatar ID for alias '{}': {}",
+                alias, e
+            )),
+        },
+    }
+}
+
+/// The one avatar a saved name query means: the only match, or the only exact name among
+/// several.
+fn pick_query_match(query: &str, mut avatars: Vec<Avatar>) -> Result<Avatar, String> {
+    if avatars.len() > 1 {
+        let exact: Vec<Avatar> = avatars
+            .iter()
+            .filter(|avatar| avatar.name.eq_ignore_ascii_case(query))
+            .cloned()
+            .collect();
+        if exact.len() == 1 {
+            avatars = exact;
+        }
+    }
+
+    match avatars.as_slice() {
+        [avatar] => Ok(avatar.clone()),
+        [] => Err(format!("No avatar found matching query '{}'", query)),
+        many => Err(format!(
+            "{} avatars match query '{}' ({}); use an ID or a more specific query",
+            many.len(),
+            query,
+            many.iter()
+                .map(|avatar| format!("{} {}", avatar.id, avatar.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Finds the one avatar a selector matches; matching none or several is an error.
+pub fn resolve_selector(selector: &Selector) -> Result<Avatar, String> {
+    let avatars = db::get_avatars_by_selector(selector)
+        .map_err(|e| format!("Error retrieving avatars for {}: {}", selector, e))?;
+    if let Selector::Query(query) = selector {
+        return pick_query_match(query, avatars);
+    }
+
+    match avatars.as_slice() {
+        [avatar] => Ok(avatar.clone()),
+        [] => Err(format!("No avatar matches {}", selector)),
+        many => Err(format!(
+            "{} avatars match {} ({})",
+            many.len(),
+            selector,
+            many.iter()
+                .map(|avatar| avatar.id.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Re-resolves selector aliases after the local database changed, warning about
+/// any that no longer match exactly one avatar.
+pub fn refresh_selector_aliases() -> Result<(), rusqlite::Error> {
+    db::create_alias_db()?;
+
+    for alias in db::get_all_aliases()? {
+        let Some(selector) = &alias.selector else {
+            continue;
+        };
+        match resolve_selector(selector) {
+            Ok(avatar) if avatar.id != alias.avatar_id => {
+                info!("Alias '{}' now selects {}", alias.name, avatar.id);
+                db::set_alias_match(&alias.name, &avatar.id, &avatar.name)?;
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Alias '{}': {}", alias.name, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// IDs of the avatars the aliases in `group` point at, re-resolving selector aliases.
+/// Selector aliases that do not match exactly one avatar are left out with a warning.
+pub fn resolve_group(group: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut ids = Vec::new();
+
+    for name in db::get_group_aliases(group)? {
+        match db::get_alias(&name)? {
+            Some(Alias {
+                selector: Some(selector),
+                ..
+            }) => match resolve_selector(&selector) {
+                Ok(avatar) => ids.push(avatar.id),
+                Err(e) => warn!("Alias '{}': {}", name, e),
+            },
+            Some(alias) => ids.push(alias.avatar_id),
+            None => {}
+        }
+    }
+
+    Ok(ids)
+}
+
+pub async fn switch_avatar(
+    configuration: Configuration,
+    avatar_id: &str,
+) -> Result<CurrentUser, apis::Error<SelectAvatarError>> {
+    info!("Switching to avatar ID: {}", avatar_id);
+
+    ratelimit::api().wait().await;
+    trace_request("select_avatar", avatar_id);
     let result = apis::avatars_api::select_avatar(&configuration, avatar_id).await;
+    trace_response("select_avatar", &result);
+
+    match &result {
+        Ok(user) => info!("Successfully switched to avatar: {}", user.current_avatar),
+        Err(e) => error!("Failed to switch avatar: {}", e),
+    }
+
+    result
+}
+
+/// Switches avatar and writes the outcome to the history table, running the configured
+/// hooks around it. A pre-switch hook can veto the switch, which is reported as such and
+/// left out of the history since no switch was attempted.
+pub async fn switch_and_record(
+    configuration: Configuration,
+    avatar_id: &str,
+    resolved_by: &str,
+    input: &str,
+) -> SwitchRecord {
+    if let Err(reason) = hooks::pre_switch(avatar_id, resolved_by, input).await {
+        warn!("Switch to {} vetoed by a pre-switch hook", avatar_id);
+        return SwitchRecord {
+            avatar_id: avatar_id.to_string(),
+            resolved_by: resolved_by.to_string(),
+            input: input.to_string(),
+            success: false,
+            vetoed: true,
+            error: Some(format!("Vetoed by pre-switch hook: {}", reason)),
+        };
+    }
+
+    switch_and_record_approved(configuration, avatar_id, resolved_by, input).await
+}
+
+/// Like [`switch_and_record`], for callers that have already run the pre-switch hooks.
+pub async fn switch_and_record_approved(
+    configuration: Configuration,
+    avatar_id: &str,
+    resolved_by: &str,
+    input: &str,
+) -> SwitchRecord {
+    let error = switch_avatar(configuration.clone(), avatar_id)
+        .await
+        .err()
+        .map(|e| e.to_string());
+
+    // Keep a name for avatars that are not ours, so history can show it.
+    if error.is_none()
+        && let Err(e) = lookup_avatar(&configuration, avatar_id).await
+    {
+        warn!("Could not look up {}: {}", avatar_id, e);
+    }
+
+    if let Err(e) = db::record_switch(avatar_id, resolved_by, input, error.as_deref()) {
+        warn!("Failed to record switch history: {}", e);
+    }
+
+    if error.is_none() {
+        hooks::post_switch(avatar_id, resolved_by, input).await;
+    }
+
+    SwitchRecord {
+        avatar_id: avatar_id.to_string(),
+        resolved_by: resolved_by.to_string(),
+        input: input.to_string(),
+        success: error.is_none(),
+        vetoed: false,
+        error,
+    }
+}
+
+/// Asks the API which avatar the logged in user is wearing right now.
+pub async fn get_current_user(configuration: &Configuration) -> Result<CurrentUser, String> {
+    ratelimit::api().wait().await;
+    trace_request("get_current_user", "");
+    let result = apis::authentication_api::get_current_user(configuration).await;
+    trace_response("get_current_user", &result);
 
     match result {
-        Ok(user) => println!("Successfully switched to avatar: {}", user.current_avatar),
-        Err(e) => eprintln!("Failed to switch avatar: {}", e),
+        Ok(EitherUserOrTwoFactor::CurrentUser(user)) => Ok(user),
+        Ok(EitherUserOrTwoFactor::RequiresTwoFactorAuth(_)) => Err(String::from(
+            "Auth cookie is invalid, please authenticate again",
+        )),
+        Err(e) => Err(format!("Failed to get current user: {}", e)),
     }
 }
+
+pub async fn get_current_avatar_id(configuration: &Configuration) -> Result<String, String> {
+    Ok(get_current_user(configuration).await?.current_avatar)
+}
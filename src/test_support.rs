@@ -0,0 +1,20 @@
+//! Setup shared by the unit tests that touch the database or the config.
+
+use std::sync::Once;
+
+use crate::config::{self, Config};
+
+/// Moves the test process into a scratch directory, so that `./avatars.db` and the other
+/// working files are not the real ones, and installs the default config. Every test that
+/// needs either calls this first; only the first call does anything.
+pub fn setup() {
+    static SETUP: Once = Once::new();
+
+    SETUP.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("vavc-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("Cannot create the test directory");
+        std::env::set_current_dir(&dir).expect("Cannot enter the test directory");
+        config::init(Config::default());
+    });
+}
@@ -0,0 +1,80 @@
+use clap::ValueEnum;
+use tracing::info;
+use vrchatapi::apis::{self, configuration::Configuration};
+use vrchatapi::models::{CurrentUser, UpdateUserRequest, UserStatus};
+
+use crate::log::{trace_request, trace_response};
+use crate::ratelimit;
+
+/// The statuses we can set ourselves; offline is decided by VRChat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Status {
+    Active,
+    JoinMe,
+    AskMe,
+    Busy,
+}
+
+impl Status {
+    /// The name used on the command line and in the database, e.g. `join-me`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Active => "active",
+            Status::JoinMe => "join-me",
+            Status::AskMe => "ask-me",
+            Status::Busy => "busy",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Status, String> {
+        Status::from_str(name, true).map_err(|_| format!("Unknown status '{}'", name))
+    }
+}
+
+/// Names a status the way the command line does, e.g. `join-me` rather than `join me`.
+pub fn status_name(status: UserStatus) -> String {
+    status.to_string().replace(' ', "-")
+}
+
+impl From<Status> for UserStatus {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Active => UserStatus::Active,
+            Status::JoinMe => UserStatus::JoinMe,
+            Status::AskMe => UserStatus::AskMe,
+            Status::Busy => UserStatus::Busy,
+        }
+    }
+}
+
+/// Changes the given profile fields of the logged in user; `None` keeps a field as it is.
+pub async fn update_profile(
+    config: &Configuration,
+    user_id: &str,
+    status: Option<UserStatus>,
+    status_description: Option<String>,
+    bio: Option<String>,
+) -> Result<CurrentUser, String> {
+    let request = UpdateUserRequest {
+        status,
+        status_description,
+        bio,
+        ..UpdateUserRequest::new()
+    };
+
+    ratelimit::api().wait().await;
+    trace_request(
+        "update_user",
+        &format!(
+            "{} {}",
+            user_id,
+            serde_json::to_string(&request).unwrap_or_default()
+        ),
+    );
+    let result = apis::users_api::update_user(config, user_id, Some(request)).await;
+    trace_response("update_user", &result);
+    let user = result.map_err(|e| format!("Failed to update profile: {}", e))?;
+
+    info!("Profile updated, status: {}", user.status);
+    Ok(user)
+}